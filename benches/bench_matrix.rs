@@ -1,12 +1,15 @@
+use criterion::BatchSize;
 use criterion::BenchmarkId;
 use criterion::Throughput;
 use criterion::{criterion_group, criterion_main, Criterion};
 use std::hint::black_box;
 use std::time::Duration;
+use xcc::Builder;
 use xcc::Color;
 use xcc::ColoredItem;
 use xcc::ItemId;
 use xcc::Matrix;
+use xcc::{Limit, Solver};
 
 pub fn sudoku_matrix(c: &mut Criterion) {
     c.bench_function("build_sudoku_matrix", |b| {
@@ -16,6 +19,19 @@ pub fn sudoku_matrix(c: &mut Criterion) {
         });
     });
 }
+pub fn builder_build(c: &mut Criterion) {
+    // The string-based builder is what most users go through, so measure
+    // its whole build step on an empty Sudoku.
+    let builder = sudoku_builder();
+    c.bench_function("builder_build", |b| {
+        b.iter_batched(
+            || builder.clone(),
+            |builder| builder.build().unwrap(),
+            BatchSize::LargeInput,
+        );
+    });
+}
+
 pub fn add_option(c: &mut Criterion) {
     let mut group = c.benchmark_group("add_option");
     group.measurement_time(Duration::from_secs(10));
@@ -37,7 +53,105 @@ pub fn add_option(c: &mut Criterion) {
     }
 }
 
-criterion_group!(benches, add_option);
+pub fn add_option_and_solve(c: &mut Criterion) {
+    // The solver walks an option's items every time it commits to it, so
+    // adding and solving wide colored options should stay linear in their
+    // size.
+    let mut group = c.benchmark_group("add_option_and_solve");
+    for size in [10, 100, 1000] {
+        group.throughput(Throughput::Elements(size as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(size), &size, |b, &n| {
+            let names = (0..n).map(|i| format!("x{i}")).collect::<Vec<_>>();
+            b.iter(|| {
+                let mut builder = Matrix::builder();
+                builder.add_primary_items(["a", "b"]);
+                builder.add_secondary_items(&names);
+                for (meaning, primary, color) in
+                    [(1, "a", "red"), (2, "b", "red"), (3, "b", "blue")]
+                {
+                    let colored = names.iter().map(|name| format!("{name}:{color}"));
+                    builder
+                        .add_option(meaning, std::iter::once(primary.to_string()).chain(colored));
+                }
+                let matrix = builder.build().unwrap();
+                let mut solver = Solver::new(&matrix);
+                solver.count_solutions(Limit::All)
+            });
+        });
+    }
+}
+
+pub fn color_aware_counts(c: &mut Criterion) {
+    // The Petersen graph, which needs 3 colors.
+    #[rustfmt::skip]
+    let edges = [
+        (0, 1), (1, 2), (2, 3), (3, 4), (4, 0),
+        (0, 5), (1, 6), (2, 7), (3, 8), (4, 9),
+        (5, 7), (7, 9), (9, 6), (6, 8), (8, 5),
+    ];
+    let matrix = xcc::samples::graph_coloring(&edges, 3);
+    let mut group = c.benchmark_group("color_aware_counts");
+    for color_aware in [false, true] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(color_aware),
+            &color_aware,
+            |b, &color_aware| {
+                b.iter(|| {
+                    let mut solver = Solver::new(&matrix);
+                    solver.set_color_aware_counts(color_aware);
+                    solver.count_solutions(Limit::All)
+                });
+            },
+        );
+    }
+}
+
+pub fn secondary_branching(c: &mut Criterion) {
+    // N queens, where the diagonals are secondary items.
+    let n = 8;
+    let mut builder = Matrix::builder();
+    builder.add_primary_items((0..n).map(|i| format!("R{i}")));
+    builder.add_primary_items((0..n).map(|i| format!("C{i}")));
+    builder.add_secondary_items((0..2 * n - 1).map(|i| format!("D{i}")));
+    builder.add_secondary_items((0..2 * n - 1).map(|i| format!("E{i}")));
+    for row in 0..n {
+        for col in 0..n {
+            let (d, e) = (row + col, n - 1 - row + col);
+            let items = [
+                format!("R{row}"),
+                format!("C{col}"),
+                format!("D{d}"),
+                format!("E{e}"),
+            ];
+            builder.add_option((row, col), items);
+        }
+    }
+    let matrix = builder.build().unwrap();
+
+    let mut group = c.benchmark_group("secondary_branching");
+    for secondary_branching in [false, true] {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(secondary_branching),
+            &secondary_branching,
+            |b, &secondary_branching| {
+                b.iter(|| {
+                    let mut solver = Solver::new(&matrix);
+                    solver.set_secondary_branching(secondary_branching);
+                    solver.count_solutions(Limit::All)
+                });
+            },
+        );
+    }
+}
+
+criterion_group!(
+    benches,
+    builder_build,
+    add_option,
+    add_option_and_solve,
+    color_aware_counts,
+    secondary_branching
+);
 criterion_main!(benches);
 
 type Items = Vec<usize>;
@@ -57,6 +171,30 @@ pub fn build_sudoku_matrix(
     matrix
 }
 
+/// Creates a builder for Sudoku without any clues, with items named the way
+/// a user of the builder might name them.
+pub fn sudoku_builder() -> Builder<usize> {
+    let mut builder = Matrix::builder();
+    for t in ["p", "r", "c", "b"] {
+        builder.add_primary_items((0..81).map(|i| format!("{t}{}{}", i / 9, i % 9)));
+    }
+    for row in 0..9 {
+        for col in 0..9 {
+            let box_num = row / 3 * 3 + col / 3;
+            for digit in 0..9 {
+                let items = [
+                    format!("p{row}{col}"),
+                    format!("r{row}{digit}"),
+                    format!("c{col}{digit}"),
+                    format!("b{box_num}{digit}"),
+                ];
+                builder.add_option(row * 81 + col * 9 + digit, items);
+            }
+        }
+    }
+    builder
+}
+
 /// Creates items and options for benchmarking.  The resulting XCC problem is
 /// isomorphic to Sudoku without any clues.  This uses the low-level API for
 /// benchmarking; see examples/sudoku.rs for a more realistic Sudoku solver.
@@ -1,4 +1,5 @@
 use clap::Parser;
+use xcc::samples::render_grid;
 use xcc::Matrix;
 
 #[derive(Parser)]
@@ -13,12 +14,11 @@ struct Cmdline {
 /// Solves the N-Queens problem: place N queens on an NxN chessboard
 /// so that no queen can attack any other queen.  Queens can attack
 /// horizontally, vertically, or diagonally.
-
 pub fn main() {
     let cmdline = Cmdline::parse();
     let n = cmdline.size;
 
-    let mut matrix = build_matrix(n);
+    let matrix = build_matrix(n);
     let start_time = std::time::Instant::now();
     let mut count = 0;
     for solution in matrix.solve_all() {
@@ -28,14 +28,7 @@ pub fn main() {
             for &Queen { row, col } in placements {
                 grid[row][col] = 'Q';
             }
-            #[allow(clippy::needless_range_loop)]
-            for row in 0..n {
-                for col in 0..n {
-                    print!("{}", grid[row][col]);
-                }
-                println!();
-            }
-            println!();
+            println!("{}", render_grid(n, n, |row, col| grid[row][col]));
         }
         count += 1;
     }
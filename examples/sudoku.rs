@@ -41,7 +41,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
 /// Returns a string containing the solution if there is exactly one solution,
 /// or an error message if there are no solutions or multiple solutions.
 pub fn solve_sudoku(input: &str) -> Result<String, &'static str> {
-    let mut matrix = build_matrix(input);
+    let matrix = build_matrix(input);
     match matrix.solve_unique() {
         xcc::Unique::None => Err("No solution"),
         xcc::Unique::One(solution) => {
@@ -61,6 +61,8 @@ pub fn solve_sudoku(input: &str) -> Result<String, &'static str> {
 
 fn build_matrix(input: &str) -> Matrix<Placement> {
     let mut builder = Matrix::builder();
+    // A puzzle with clashing clues has no solution, rather than being an error.
+    builder.allow_unused_primaries(true);
 
     // Create items describing how the Sudoku grid must be filled.
     // First, each cell must be filled with a number. These constraints
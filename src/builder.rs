@@ -1,23 +1,165 @@
 use super::Matrix;
-use crate::types::Color;
-use crate::types::ItemId;
+use crate::collections::{HashMap, HashSet};
+use crate::types::ColorInterner;
+use crate::types::{split_item, ItemColorName, ItemId, OptionId, SecondaryMode};
 use crate::ColoredItem;
-use std::collections::HashMap;
-use std::fmt::Debug;
-use std::fmt::Display;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::fmt::Display;
 
+/// An error returned by `Builder::build()`, `Builder::add_all_different()`,
+/// or `ColoredItem::parse()`.
 #[derive(Debug, thiserror::Error)]
 pub enum BuildError {
+    /// An option uses an item that was never declared.
     #[error("Item {0} is used in an option, but not declared")]
     ItemNotDeclared(String),
+    /// An item is declared as both primary and secondary.
     #[error("Item {0} is declared as both primary and secondary")]
     ItemDeclaredTwice(String),
+    /// No primary items were declared.
     #[error("No primary items declared")]
     NoPrimaryItems,
+    /// A primary item is not used in any option.
     #[error("Primary item {0} is not used in any option, so no solutions are possible.")]
     PrimaryItemNotUsed(String),
+    /// No options were declared.
     #[error("No options declared")]
     NoOptions,
+    /// A primary item is declared more than once.
+    #[error("Primary item {0} is declared more than once")]
+    DuplicatePrimaryItem(String),
+    /// A secondary item is declared more than once.
+    #[error("Secondary item {0} is declared more than once")]
+    DuplicateSecondaryItem(String),
+    /// An alias refers to an item that was never declared.
+    #[error("Alias {0} refers to an item that is not declared")]
+    AliasTargetNotDeclared(String),
+    /// An alias has the same name as a declared item.
+    #[error("Alias {0} has the same name as a declared item")]
+    AliasShadowsItem(String),
+    /// An alias is declared more than once.
+    #[error("Alias {0} is declared more than once")]
+    DuplicateAlias(String),
+    /// An option added by `add_option_ids()` uses an item ID past the end of
+    /// the declared items.
+    #[error("Item ID {0} is used in an option, but not declared")]
+    ItemIdOutOfRange(usize),
+    /// An item name given to `add_option_with()` contains `:`, which
+    /// separates names from colors.
+    #[error("Item name {0} contains ':'")]
+    InvalidItemName(String),
+    /// An option colors a primary item that wasn't declared with
+    /// `add_colored_primary_item()`.
+    #[error("Primary item {0} is colored in an option")]
+    PrimaryItemColored(String),
+    /// An item given to `ColoredItem::parse()` forbids a color, as in
+    /// `x:!red`, which only the builder supports.
+    #[error("Item {0} forbids a color, which a ColoredItem can't express")]
+    ForbiddenColorUnsupported(String),
+    /// A cell given to `add_all_different()` can already take a value that
+    /// isn't one of the new group's values.
+    #[error("Cell {0} can take a value that isn't one of its group's values")]
+    ValueNotInGroup(String),
+}
+
+/// The kind of a `BuildError`, for callers that want to react to errors
+/// without matching on their messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ErrorKind {
+    /// See `BuildError::ItemNotDeclared`.
+    ItemNotDeclared,
+    /// See `BuildError::ItemDeclaredTwice`.
+    ItemDeclaredTwice,
+    /// See `BuildError::NoPrimaryItems`.
+    NoPrimaryItems,
+    /// See `BuildError::PrimaryItemNotUsed`.
+    PrimaryItemNotUsed,
+    /// See `BuildError::NoOptions`.
+    NoOptions,
+    /// See `BuildError::DuplicatePrimaryItem`.
+    DuplicatePrimaryItem,
+    /// See `BuildError::DuplicateSecondaryItem`.
+    DuplicateSecondaryItem,
+    /// See `BuildError::AliasTargetNotDeclared`.
+    AliasTargetNotDeclared,
+    /// See `BuildError::AliasShadowsItem`.
+    AliasShadowsItem,
+    /// See `BuildError::DuplicateAlias`.
+    DuplicateAlias,
+    /// See `BuildError::ItemIdOutOfRange`.
+    ItemIdOutOfRange,
+    /// See `BuildError::InvalidItemName`.
+    InvalidItemName,
+    /// See `BuildError::PrimaryItemColored`.
+    PrimaryItemColored,
+    /// See `BuildError::ForbiddenColorUnsupported`.
+    ForbiddenColorUnsupported,
+    /// See `BuildError::ValueNotInGroup`.
+    ValueNotInGroup,
+}
+
+impl BuildError {
+    /// Returns the kind of this error.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::{ErrorKind, Matrix};
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_item("a");
+    /// builder.add_option(1, ["a", "b:red"]);
+    /// let err = builder.build().unwrap_err();
+    /// assert_eq!(err.kind(), ErrorKind::ItemNotDeclared);
+    /// assert_eq!(err.item_name(), Some("b"));
+    /// ```
+    #[must_use]
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            BuildError::ItemNotDeclared(_) => ErrorKind::ItemNotDeclared,
+            BuildError::ItemDeclaredTwice(_) => ErrorKind::ItemDeclaredTwice,
+            BuildError::NoPrimaryItems => ErrorKind::NoPrimaryItems,
+            BuildError::PrimaryItemNotUsed(_) => ErrorKind::PrimaryItemNotUsed,
+            BuildError::NoOptions => ErrorKind::NoOptions,
+            BuildError::DuplicatePrimaryItem(_) => ErrorKind::DuplicatePrimaryItem,
+            BuildError::DuplicateSecondaryItem(_) => ErrorKind::DuplicateSecondaryItem,
+            BuildError::AliasTargetNotDeclared(_) => ErrorKind::AliasTargetNotDeclared,
+            BuildError::AliasShadowsItem(_) => ErrorKind::AliasShadowsItem,
+            BuildError::DuplicateAlias(_) => ErrorKind::DuplicateAlias,
+            BuildError::ItemIdOutOfRange(_) => ErrorKind::ItemIdOutOfRange,
+            BuildError::InvalidItemName(_) => ErrorKind::InvalidItemName,
+            BuildError::PrimaryItemColored(_) => ErrorKind::PrimaryItemColored,
+            BuildError::ForbiddenColorUnsupported(_) => ErrorKind::ForbiddenColorUnsupported,
+            BuildError::ValueNotInGroup(_) => ErrorKind::ValueNotInGroup,
+        }
+    }
+
+    /// Returns the name of the offending item, if the error concerns a
+    /// particular item.
+    #[must_use]
+    pub fn item_name(&self) -> Option<&str> {
+        match self {
+            BuildError::ItemNotDeclared(name)
+            | BuildError::ItemDeclaredTwice(name)
+            | BuildError::PrimaryItemNotUsed(name)
+            | BuildError::DuplicatePrimaryItem(name)
+            | BuildError::DuplicateSecondaryItem(name)
+            | BuildError::AliasTargetNotDeclared(name)
+            | BuildError::AliasShadowsItem(name)
+            | BuildError::DuplicateAlias(name)
+            | BuildError::InvalidItemName(name)
+            | BuildError::PrimaryItemColored(name)
+            | BuildError::ForbiddenColorUnsupported(name)
+            | BuildError::ValueNotInGroup(name) => Some(name),
+            BuildError::NoPrimaryItems
+            | BuildError::NoOptions
+            | BuildError::ItemIdOutOfRange(_) => None,
+        }
+    }
 }
 
 /// A builder for a matrix.
@@ -61,7 +203,32 @@ pub enum BuildError {
 pub struct Builder<T> {
     primary_items: Vec<String>,
     secondary_items: Vec<String>,
-    options: Vec<(T, Vec<String>)>,
+    required_items: Vec<String>,
+    /// Secondary items that solutions should cover if they can.  These are
+    /// also in `secondary_items`.
+    soft_items: Vec<String>,
+    secondary_mode: SecondaryMode,
+    /// Whether `build()` accepts primary items that no option uses.
+    allow_unused_primaries: bool,
+    /// Primary items that options may color.  These are also in
+    /// `primary_items`.
+    colored_primary_items: Vec<String>,
+    /// Alternative names for items, as (alias, canonical name) pairs.
+    aliases: Vec<(String, String)>,
+    /// Items with several color channels, as (item, channel names) pairs.
+    channels: Vec<(String, Vec<String>)>,
+    options: Vec<(T, Vec<OptionItem>)>,
+    /// Options given by item ID, each with the number of string options
+    /// added before it, so that `build()` can keep them in order.
+    id_options: Vec<(usize, T, Vec<ColoredItem>)>,
+    /// Assigns colors to the color names in the options.
+    colors: ColorInterner,
+    /// Number of auxiliary constraints added so far, used to give their
+    /// items unique names.
+    num_aux_groups: usize,
+    /// For each cell of an all-different group, the value of each of its
+    /// assignment options along with the option's index.
+    assignments: HashMap<String, Vec<(String, usize)>>,
 }
 
 impl<T> Default for Builder<T> {
@@ -69,7 +236,18 @@ impl<T> Default for Builder<T> {
         Self {
             primary_items: Vec::new(),
             secondary_items: Vec::new(),
+            required_items: Vec::new(),
+            soft_items: Vec::new(),
+            secondary_mode: SecondaryMode::default(),
+            allow_unused_primaries: false,
+            colored_primary_items: Vec::new(),
+            aliases: Vec::new(),
+            channels: Vec::new(),
             options: Vec::new(),
+            id_options: Vec::new(),
+            colors: ColorInterner::new(),
+            num_aux_groups: 0,
+            assignments: HashMap::new(),
         }
     }
 }
@@ -81,6 +259,73 @@ impl<T> Builder<T> {
         Self::default()
     }
 
+    /// Creates a new Builder that assigns colors with `colors`, so that color
+    /// names already in it keep their IDs, and new ones get the next IDs
+    /// along.  Use `build_with_color_interner()` to get the interner back,
+    /// ready to seed the next builder.
+    ///
+    /// # Example
+    /// ```
+    /// use xcc::{Builder, ColorInterner, ItemId};
+    ///
+    /// let mut colors = ColorInterner::new();
+    /// let blue = colors.intern("blue");
+    ///
+    /// let mut builder = Builder::with_color_interner(colors);
+    /// builder.add_primary_item("p");
+    /// builder.add_secondary_item("x");
+    /// builder.add_option(1, ["p", "x:red"]);
+    /// builder.add_option(2, ["p", "x:blue"]);
+    /// let (matrix, colors) = builder.build_with_color_interner().unwrap();
+    ///
+    /// assert_eq!(matrix.colors_for_item(ItemId::new(1)), [blue, colors.get("red").unwrap()]);
+    /// assert_eq!(colors.len(), 2);
+    /// ```
+    #[must_use]
+    pub fn with_color_interner(colors: ColorInterner) -> Self {
+        Self {
+            colors,
+            ..Self::default()
+        }
+    }
+
+    /// Creates a Builder with the given items and options in a single call.
+    ///
+    /// This is shorthand for calling `add_primary_items()`,
+    /// `add_secondary_items()`, and then `add_option()` for each option.
+    /// Validation still happens when you call `build()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any primary item is colored, just like `add_option()`.
+    ///
+    /// # Example
+    /// ```
+    /// use xcc::Builder;
+    ///
+    /// let builder = Builder::from_options(
+    ///     &["p", "q"],
+    ///     &["x"],
+    ///     [
+    ///         (1, vec!["p".to_string(), "x:A".to_string()]),
+    ///         (2, vec!["q".to_string(), "x:A".to_string()]),
+    ///     ],
+    /// );
+    /// let matrix = builder.build().expect("could not build matrix");
+    /// assert_eq!(matrix.solve_all().len(), 1);
+    /// ```
+    pub fn from_options(
+        primary: &[&str],
+        secondary: &[&str],
+        options: impl IntoIterator<Item = (T, Vec<String>)>,
+    ) -> Self {
+        let mut builder = Self::new();
+        builder.add_primary_items(primary);
+        builder.add_secondary_items(secondary);
+        builder.add_options(options);
+        builder
+    }
+
     /// Adds primary items to the matrix.
     pub fn add_primary_items<S: Display>(&mut self, items: impl IntoIterator<Item = S>) {
         self.primary_items
@@ -103,17 +348,344 @@ impl<T> Builder<T> {
         self.secondary_items.push(item.to_string());
     }
 
+    /// Adds an item that can be colored like a secondary item, but must be
+    /// covered like a primary item.
+    ///
+    /// Every solution contains at least one option with this item.  If the
+    /// item is colored, then several options can share it as long as they all
+    /// give it the same color; if it is uncolored, exactly one option uses it.
+    ///
+    /// # Example
+    /// ```
+    /// use xcc::Matrix;
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["a", "b"]);
+    /// builder.add_required_colored_item("r");
+    /// builder.add_option(1, ["a"]);
+    /// builder.add_option(2, ["b"]);
+    /// builder.add_option(3, ["a", "r:red"]);
+    /// builder.add_option(4, ["b", "r:blue"]);
+    /// let matrix = builder.build().unwrap();
+    ///
+    /// // Options 1 and 2 don't use "r", and options 3 and 4 disagree on its
+    /// // color, so neither pair is a solution.
+    /// let mut solutions: Vec<_> = matrix
+    ///     .solve_all()
+    ///     .iter()
+    ///     .map(|s| {
+    ///         let mut meanings = s.meanings(&matrix);
+    ///         meanings.sort();
+    ///         meanings
+    ///     })
+    ///     .collect();
+    /// solutions.sort();
+    /// assert_eq!(solutions, [[&1, &4], [&2, &3]]);
+    /// ```
+    pub fn add_required_colored_item(&mut self, item: impl Display) {
+        self.required_items.push(item.to_string());
+    }
+
+    /// Adds a secondary item with several independent color channels.  Each
+    /// channel is colored separately, so options that agree on one channel
+    /// can disagree on another.
+    ///
+    /// The channels are secondary items called `item/channel`, and options
+    /// can use them like any other item, with or without a color.  An option
+    /// that uses plain `item` uses every channel, so `item` on its own
+    /// claims all of them, and `item:color` gives them all the same color.
+    /// Declare the item before adding options that use it.
+    ///
+    /// # Example
+    /// ```
+    /// use xcc::Matrix;
+    ///
+    /// // A crossword cell that is in both an across word and a down word.
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["across", "down", "shade"]);
+    /// builder.add_channeled_item("cell", &["across", "down"]);
+    /// builder.add_option("CAT", ["across", "cell/across:T"]);
+    /// builder.add_option("COT", ["across", "cell/across:O"]);
+    /// builder.add_option("TEN", ["down", "cell/down:N"]);
+    /// // Shading the cell rules out any letter in it.
+    /// builder.add_option("shaded", ["shade", "cell"]);
+    /// builder.add_option("unshaded", ["shade"]);
+    /// let matrix = builder.build().unwrap();
+    ///
+    /// let mut solutions: Vec<_> = matrix
+    ///     .solve_all()
+    ///     .iter()
+    ///     .map(|s| {
+    ///         let mut meanings = s.meanings(&matrix);
+    ///         meanings.sort();
+    ///         meanings
+    ///     })
+    ///     .collect();
+    /// solutions.sort();
+    /// assert_eq!(solutions, [[&"CAT", &"TEN", &"unshaded"], [&"COT", &"TEN", &"unshaded"]]);
+    /// ```
+    pub fn add_channeled_item(&mut self, item: impl Display, channels: &[&str]) {
+        let item = item.to_string();
+        for channel in channels {
+            self.add_secondary_item(format!("{item}/{channel}"));
+        }
+        let channels = channels.iter().map(ToString::to_string).collect();
+        self.channels.push((item, channels));
+    }
+
+    /// Adds a soft primary item: one that solutions should cover if they
+    /// can, but don't have to.  It is used at most once, like an uncolored
+    /// secondary item, and `Solver::solve_max_soft()` finds a solution that
+    /// covers as many soft items as possible.  The other solving methods
+    /// treat it as an ordinary secondary item.
+    ///
+    /// # Example
+    /// ```
+    /// use xcc::{ItemId, Matrix};
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_item("a");
+    /// builder.add_soft_primary_item("s");
+    /// builder.add_option(1, ["a"]);
+    /// builder.add_option(2, ["a", "s"]);
+    /// let matrix = builder.build().unwrap();
+    /// assert!(matrix.is_soft(ItemId::new(1)));
+    /// assert_eq!(matrix.solve_all().len(), 2);
+    /// ```
+    pub fn add_soft_primary_item(&mut self, item: impl Display) {
+        let item = item.to_string();
+        self.soft_items.push(item.clone());
+        self.secondary_items.push(item);
+    }
+
+    /// Chooses how uncolored secondary items are treated.  The default,
+    /// `SecondaryMode::AtMostOnce`, allows each one to appear in at most one
+    /// option of a solution.  With `SecondaryMode::Free`, they impose no
+    /// constraint at all.  Required items are not affected.
+    ///
+    /// # Example
+    /// ```
+    /// use xcc::{Matrix, SecondaryMode};
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["a", "b"]);
+    /// builder.add_secondary_item("x");
+    /// builder.add_option(1, ["a", "x"]);
+    /// builder.add_option(2, ["b", "x"]);
+    /// builder.secondary_mode(SecondaryMode::Free);
+    /// let matrix = builder.build().unwrap();
+    ///
+    /// // Both options use x, which is fine when x is free.
+    /// assert_eq!(matrix.solve_all().len(), 1);
+    /// ```
+    pub fn secondary_mode(&mut self, mode: SecondaryMode) {
+        self.secondary_mode = mode;
+    }
+
+    /// Sets whether `build()` accepts primary items that no option uses.
+    /// Such an item can never be covered, so the matrix has no solutions,
+    /// and by default `build()` reports it as
+    /// `BuildError::PrimaryItemNotUsed`.  Turn this on when an empty problem
+    /// is a legitimate outcome, such as a piece that fits nowhere on a board.
+    ///
+    /// # Example
+    /// ```
+    /// use xcc::{ErrorKind, Matrix};
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["a", "b"]);
+    /// builder.add_option(1, ["a"]);
+    /// let err = builder.clone().build().unwrap_err();
+    /// assert_eq!(err.kind(), ErrorKind::PrimaryItemNotUsed);
+    ///
+    /// builder.allow_unused_primaries(true);
+    /// let matrix = builder.build().unwrap();
+    /// assert!(!matrix.is_satisfiable());
+    /// ```
+    pub fn allow_unused_primaries(&mut self, yes: bool) {
+        self.allow_unused_primaries = yes;
+    }
+
+    /// Makes `alias` another name for the item `canonical`, so that an option
+    /// can use either name (with or without a color) to mean the same item.
+    /// The canonical item only needs to be declared by the time `build()` is
+    /// called.  An alias can only be declared once, and can only be colored
+    /// where the canonical item could be.
+    ///
+    /// # Example
+    /// ```
+    /// use xcc::Matrix;
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_alias("row 1", "R1");
+    /// builder.add_primary_items(["R1", "C1"]);
+    /// builder.add_option(1, ["row 1", "C1"]);
+    /// let matrix = builder.build().unwrap();
+    /// assert_eq!(matrix.num_items(), 2);
+    /// assert_eq!(matrix.solve_all().len(), 1);
+    /// ```
+    pub fn add_alias(&mut self, alias: &str, canonical: &str) {
+        self.aliases
+            .push((alias.to_string(), canonical.to_string()));
+    }
+
+    /// Adds a primary item that options are allowed to color.  Like any
+    /// primary item, exactly one option in each solution covers it.  The
+    /// color that option gives it is committed in the same way as a
+    /// secondary item's color, so it is reported by
+    /// `Solver::solve_each_with_colors()` and `Solver::completions_agree()`.
+    ///
+    /// Since only one option in a solution uses the item, there are no other
+    /// uses for its color to agree with.  In particular, the item can't also
+    /// be used as a secondary item: declaring it as one makes `build()`
+    /// return `BuildError::ItemDeclaredTwice`.
+    ///
+    /// # Example
+    /// ```
+    /// use xcc::{Color, ItemId, Limit, Matrix, Solver};
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_colored_primary_item("cell");
+    /// builder.add_option("red", ["cell:red"]);
+    /// builder.add_option("blue", ["cell:blue"]);
+    /// let matrix = builder.build().unwrap();
+    ///
+    /// let mut colors = Vec::new();
+    /// Solver::new(&matrix).solve_each_with_colors(Limit::All, |_, committed| {
+    ///     colors.push(committed[&ItemId::new(0)]);
+    /// });
+    /// colors.sort();
+    /// assert_eq!(colors, [Color::new(0), Color::new(1)]);
+    /// ```
+    pub fn add_colored_primary_item(&mut self, item: impl Display) {
+        let name = item.to_string();
+        self.primary_items.push(name.clone());
+        self.colored_primary_items.push(name);
+    }
+
+    /// Adds primary items to the matrix, taking ownership of the names.
+    ///
+    /// This is the same as `add_primary_items()`, but avoids copying names
+    /// that are already `String`s.
+    pub fn add_primary_items_owned(&mut self, items: Vec<String>) {
+        self.primary_items.extend(items);
+    }
+
+    /// Adds secondary items to the matrix, taking ownership of the names.
+    ///
+    /// This is the same as `add_secondary_items()`, but avoids copying names
+    /// that are already `String`s.
+    pub fn add_secondary_items_owned(&mut self, items: Vec<String>) {
+        self.secondary_items.extend(items);
+    }
+
     /// Adds an option to the matrix.
     ///
+    /// Each item is written `name` or `name:color`.  A secondary item can
+    /// also be written `name:!color`, meaning that the option doesn't use
+    /// the item, but rules out `color` for it: other options in the same
+    /// solution can give it any other color.
+    ///
     /// # Panics
     ///
-    /// Panics if any primary item is colored.
+    /// Panics if any primary item is colored, unless it was declared with
+    /// `add_colored_primary_item()`.
+    ///
+    /// # Example
+    /// ```
+    /// use xcc::Matrix;
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["p", "q"]);
+    /// builder.add_secondary_item("x");
+    /// builder.add_option("p red", ["p", "x:red"]);
+    /// builder.add_option("p blue", ["p", "x:blue"]);
+    /// builder.add_option("q not red", ["q", "x:!red"]);
+    /// let matrix = builder.build().unwrap();
+    /// assert_eq!(matrix.solve_all_meanings(), [[&"q not red", &"p blue"]]);
+    /// ```
     pub fn add_option<S: Display>(&mut self, meaning: T, items: impl IntoIterator<Item = S>) {
         let items: Vec<_> = items.into_iter().map(|i| i.to_string()).collect();
+        self.add_option_owned(meaning, items);
+    }
+
+    /// Adds an option given as (item name, color) pairs, where `None` means
+    /// the item is uncolored.  Colors can be any `Display` type, such as
+    /// numbers or enums, and are interned by their string form, so `Some(3)`
+    /// here is the same color as `x:3` in `add_option()`.
+    ///
+    /// An item name containing `:` makes `build()` return
+    /// `BuildError::InvalidItemName`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any primary item is colored, unless it was declared with
+    /// `add_colored_primary_item()`.
+    ///
+    /// # Example
+    /// ```
+    /// use xcc::{ItemId, Matrix};
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["a", "b"]);
+    /// builder.add_secondary_item("x");
+    /// builder.add_option_with(1, [("a", None), ("x", Some(7))]);
+    /// builder.add_option(2, ["b", "x:7"]);
+    /// let matrix = builder.build().unwrap();
+    /// assert_eq!(matrix.colors_for_item(ItemId::new(2)).len(), 1);
+    /// assert_eq!(matrix.solve_all().len(), 1);
+    /// ```
+    pub fn add_option_with<S: Display, C: Display>(
+        &mut self,
+        meaning: T,
+        items: impl IntoIterator<Item = (S, Option<C>)>,
+    ) {
+        let items = items
+            .into_iter()
+            .map(|(name, color)| OptionItem {
+                name: name.to_string(),
+                color: color.map_or(ItemColor::None, |color| ItemColor::Is(color.to_string())),
+            })
+            .collect();
+        self.push_option(meaning, items);
+    }
+
+    /// Adds an option to the matrix, taking ownership of the item names.
+    ///
+    /// This is the same as `add_option()`, but avoids copying names that are
+    /// already `String`s, such as ones you have just parsed from a file.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any primary item is colored, unless it was declared with
+    /// `add_colored_primary_item()`.
+    ///
+    /// # Example
+    /// ```
+    /// use xcc::Matrix;
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items_owned(vec!["a".to_string(), "b".to_string()]);
+    /// builder.add_option_owned(1, vec!["a".to_string(), "b".to_string()]);
+    /// let matrix = builder.build().unwrap();
+    /// assert_eq!(matrix.solve_all().len(), 1);
+    /// ```
+    pub fn add_option_owned(&mut self, meaning: T, items: Vec<String>) {
+        let items = items.into_iter().map(OptionItem::parse).collect();
+        self.push_option(meaning, items);
+    }
+
+    /// Adds an option whose items have already been split into names and
+    /// colors.
+    fn push_option(&mut self, meaning: T, mut items: Vec<OptionItem>) {
+        if !self.channels.is_empty() {
+            items = self.expand_channels(items);
+        }
         for item in &items {
-            if let Some((item_name, _color)) = item.split_once(':') {
+            if let ItemColor::Is(_) | ItemColor::IsNot(_) = item.color {
                 assert!(
-                    !self.primary_items.contains(&item_name.to_string()),
+                    !self.primary_items.contains(&item.name)
+                        || self.colored_primary_items.contains(&item.name),
                     "Primary items cannot be colored: {item} in {items:?}"
                 );
             }
@@ -121,6 +693,316 @@ impl<T> Builder<T> {
         self.options.push((meaning, items));
     }
 
+    /// Adds an option whose items are given by ID rather than by name,
+    /// skipping the string parsing and name lookups of `add_option()`.  This
+    /// is meant for programs that generate very large problems.
+    ///
+    /// Item IDs number the primary items first, then the secondary items,
+    /// in the order they were declared.  Colors are used as given, so use
+    /// the same `ColorInterner` as the builder (see `with_color_interner()`)
+    /// if you also add options by name.  `build()` checks that every ID
+    /// refers to a declared item, and that only colored primary items are
+    /// given colors, as `add_option()` does.
+    ///
+    /// These options keep their place among options added by name, but
+    /// they aren't seen by `add_group()`, `diff()` or `dump_knuth_format()`.
+    ///
+    /// # Example
+    /// ```
+    /// use xcc::{ColoredItem, ErrorKind, ItemId, Matrix};
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["a", "b"]);
+    /// builder.add_option(1, ["a"]);
+    /// builder.add_option_ids(2, &[ColoredItem::new(ItemId::new(1))]);
+    /// let matrix = builder.clone().build().unwrap();
+    /// assert_eq!(matrix.solve_all_meanings(), [[&1, &2]]);
+    ///
+    /// builder.add_option_ids(3, &[ColoredItem::new(ItemId::new(2))]);
+    /// assert_eq!(builder.build().unwrap_err().kind(), ErrorKind::ItemIdOutOfRange);
+    /// ```
+    pub fn add_option_ids(&mut self, meaning: T, items: &[ColoredItem]) {
+        self.id_options
+            .push((self.options.len(), meaning, items.to_vec()));
+    }
+
+    /// Replaces each use of a channeled item in an option by uses of all of
+    /// its channels.  See `add_channeled_item()`.
+    fn expand_channels(&self, items: Vec<OptionItem>) -> Vec<OptionItem> {
+        let mut expanded = Vec::with_capacity(items.len());
+        for item in items {
+            match self
+                .channels
+                .iter()
+                .find(|(channeled, _)| *channeled == item.name)
+            {
+                Some((_, channels)) => {
+                    for channel in channels {
+                        expanded.push(OptionItem {
+                            name: format!("{}/{channel}", item.name),
+                            color: item.color.clone(),
+                        });
+                    }
+                }
+                None => expanded.push(item),
+            }
+        }
+        expanded
+    }
+
+    /// Adds many options at once, taking ownership of their item names.  This
+    /// is the same as calling `add_option_owned()` for each one, but reserves
+    /// room for them up front when the iterator knows its length.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any primary item is colored, unless it was declared with
+    /// `add_colored_primary_item()`.
+    ///
+    /// # Example
+    /// ```
+    /// use xcc::Matrix;
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["a", "b"]);
+    /// builder.add_options((0..2).map(|i| (i, vec!["a".to_string(), "b".to_string()])));
+    /// let matrix = builder.build().unwrap();
+    /// assert_eq!(matrix.solve_all().len(), 2);
+    /// ```
+    pub fn add_options(&mut self, options: impl IntoIterator<Item = (T, Vec<String>)>) {
+        let options = options.into_iter();
+        self.reserve_options(options.size_hint().0);
+        for (meaning, items) in options {
+            self.add_option_owned(meaning, items);
+        }
+    }
+
+    /// Reserves room for at least `additional` more options, so that adding
+    /// a known number of options doesn't reallocate along the way.
+    pub fn reserve_options(&mut self, additional: usize) {
+        self.options.reserve(additional);
+    }
+
+    /// Adds an option whose item names are produced by filling in a template.
+    /// Each `{key}` in a template entry is replaced by `subst[key]`; any
+    /// placeholder without an entry in `subst` is left as it is.  The
+    /// entries are filled in one pass, so a value that contains `{key}` is
+    /// used as it is.
+    ///
+    /// This is handy when many options have the same shape and differ only in
+    /// a few numbers, as in Sudoku.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any primary item is colored, unless it was declared with
+    /// `add_colored_primary_item()`.
+    ///
+    /// # Example
+    /// ```
+    /// use xcc::collections::HashMap;
+    /// use xcc::Matrix;
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["R1", "C2"]);
+    /// builder.add_secondary_item("x");
+    /// let subst = HashMap::from([("row", "1".to_string()), ("col", "2".to_string())]);
+    /// builder.add_option_template(1, &["R{row}", "C{col}", "x:{row}{col}"], &subst);
+    /// assert!(builder.dump_knuth_format().ends_with("R1 C2 x:12\n"));
+    /// ```
+    pub fn add_option_template(
+        &mut self,
+        meaning: T,
+        template: &[&str],
+        subst: &HashMap<&str, String>,
+    ) {
+        let items = template
+            .iter()
+            .map(|&item| fill_template(item, subst))
+            .collect();
+        self.add_option_owned(meaning, items);
+    }
+
+    /// Puts the options that use any of the items in `option_tags` into a
+    /// group called `group_name`, so that a solution can't mix options from
+    /// different groups.  Options that aren't in any group can still be
+    /// combined with anything.
+    ///
+    /// This works by adding a secondary item called `#group`, and giving it
+    /// the color `group_name` in each option in the group.  Matching on tags
+    /// ignores colors, so `x` tags an option that uses `x:A`.  Only options
+    /// that have already been added are grouped, and an option that is
+    /// tagged by more than one group stays in the first of them.  Options
+    /// added with `add_option_ids()` have no item names to match, so they
+    /// are never grouped.
+    ///
+    /// # Example
+    /// ```
+    /// use xcc::Matrix;
+    ///
+    /// // Two meetings, each in one of two buildings at one of two times.
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["m1", "m2"]);
+    /// builder.add_secondary_items(["north.am", "north.pm", "south.am", "south.pm"]);
+    /// for meeting in ["m1", "m2"] {
+    ///     for room in ["north.am", "north.pm", "south.am", "south.pm"] {
+    ///         builder.add_option((meeting, room), [meeting, room]);
+    ///     }
+    /// }
+    /// // Keep both meetings in the same building.
+    /// builder.add_group("north", &["north.am", "north.pm"]);
+    /// builder.add_group("south", &["south.am", "south.pm"]);
+    /// let matrix = builder.build().unwrap();
+    ///
+    /// let solutions = matrix.solve_all();
+    /// assert_eq!(solutions.len(), 4);
+    /// for solution in solutions {
+    ///     let meanings = solution.meanings(&matrix);
+    ///     assert_eq!(meanings[0].1[..5], meanings[1].1[..5]);
+    /// }
+    /// ```
+    pub fn add_group(&mut self, group_name: &str, option_tags: &[&str]) {
+        const GROUP_ITEM: &str = "#group";
+        if !self.secondary_items.iter().any(|item| item == GROUP_ITEM) {
+            self.add_secondary_item(GROUP_ITEM);
+        }
+        for (_, items) in &mut self.options {
+            if items.iter().any(|item| item.name == GROUP_ITEM) {
+                continue;
+            }
+            if items
+                .iter()
+                .any(|item| option_tags.contains(&item.name.as_str()))
+            {
+                items.push(OptionItem {
+                    name: GROUP_ITEM.to_string(),
+                    color: ItemColor::Is(group_name.to_string()),
+                });
+            }
+        }
+    }
+
+    /// Runs the same validations as `build()`, without consuming the builder
+    /// or constructing the matrix.  This is cheap enough to call as you go
+    /// when generating a large problem.
+    ///
+    /// # Errors
+    ///
+    /// Returns the error that `build()` would return, if any.
+    ///
+    /// # Example
+    /// ```
+    /// use xcc::{ErrorKind, Matrix};
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_item("a");
+    /// builder.add_option(1, ["a", "b"]);
+    /// assert_eq!(builder.check().unwrap_err().kind(), ErrorKind::ItemNotDeclared);
+    ///
+    /// builder.add_secondary_item("b");
+    /// assert!(builder.check().is_ok());
+    /// ```
+    pub fn check(&self) -> Result<(), BuildError> {
+        let primary_items: &[String] = &self.primary_items;
+        let secondary_items: &[String] = &self.secondary_items;
+        let required_items: &[String] = &self.required_items;
+        if let Some(name) = find_duplicate(primary_items.iter()) {
+            return Err(BuildError::DuplicatePrimaryItem(name.to_string()));
+        }
+        if let Some(name) = find_duplicate(secondary_items.iter().chain(required_items)) {
+            return Err(BuildError::DuplicateSecondaryItem(name.to_string()));
+        }
+        let primary_names: HashSet<&String> = primary_items.iter().collect();
+        if let Some(name) = secondary_items
+            .iter()
+            .chain(required_items)
+            .find(|name| primary_names.contains(name))
+        {
+            return Err(BuildError::ItemDeclaredTwice(name.clone()));
+        }
+
+        let item_names: HashSet<&str> = primary_items
+            .iter()
+            .chain(secondary_items)
+            .chain(required_items)
+            .map(String::as_str)
+            .collect();
+        if let Some(alias) = find_duplicate(self.aliases.iter().map(|(alias, _)| alias)) {
+            return Err(BuildError::DuplicateAlias(alias.to_string()));
+        }
+        for (alias, canonical) in &self.aliases {
+            if item_names.contains(alias.as_str()) {
+                return Err(BuildError::AliasShadowsItem(alias.clone()));
+            }
+            if !item_names.contains(canonical.as_str()) {
+                return Err(BuildError::AliasTargetNotDeclared(canonical.clone()));
+            }
+        }
+
+        let header_names = self.header_names();
+        let mut used = vec![false; primary_items.len()];
+        // Marks a use of the item with the given index, which must only have
+        // a color if it's secondary or a colored primary.
+        let mut mark_used = |index: usize, colored: bool| match used.get_mut(index) {
+            Some(_) if colored && !self.colored_primary_items.contains(&primary_items[index]) => {
+                Err(BuildError::PrimaryItemColored(primary_items[index].clone()))
+            }
+            Some(used) => {
+                *used = true;
+                Ok(())
+            }
+            None => Ok(()),
+        };
+        for (_, option) in &self.options {
+            for item in option {
+                let name = item.name.as_str();
+                if name.contains(':') {
+                    return Err(BuildError::InvalidItemName(name.to_string()));
+                }
+                match header_names.get(name) {
+                    Some(item_id) => {
+                        mark_used(item_id.index(), item.color != ItemColor::None)?;
+                    }
+                    None => return Err(BuildError::ItemNotDeclared(name.to_string())),
+                }
+            }
+        }
+        let num_items = primary_items.len() + secondary_items.len() + required_items.len();
+        for (_, _, option) in &self.id_options {
+            for item in option {
+                let index = item.item().index();
+                if index >= num_items {
+                    return Err(BuildError::ItemIdOutOfRange(index));
+                }
+                mark_used(index, item.color().is_some())?;
+            }
+        }
+        if !self.allow_unused_primaries {
+            if let Some(i) = used.iter().position(|&used| !used) {
+                return Err(BuildError::PrimaryItemNotUsed(primary_items[i].clone()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Maps each item name to its ID: primary items first, then secondary,
+    /// then required, followed by the aliases.
+    fn header_names(&self) -> HashMap<&str, ItemId> {
+        let mut header_names: HashMap<&str, ItemId> = self
+            .primary_items
+            .iter()
+            .chain(&self.secondary_items)
+            .chain(&self.required_items)
+            .enumerate()
+            .map(|(i, name)| (name.as_str(), ItemId::new(i)))
+            .collect();
+        for (alias, canonical) in &self.aliases {
+            if let Some(&item_id) = header_names.get(canonical.as_str()) {
+                header_names.entry(alias).or_insert(item_id);
+            }
+        }
+        header_names
+    }
+
     /// Builds the matrix, returning a Result. If there is a problem, this will
     /// return a {`BuildError`}.
     ///
@@ -132,50 +1014,546 @@ impl<T> Builder<T> {
     /// * An item is declared as both primary and secondary.
     /// * No options have been declared.
     /// * An option uses an item that has not been declared.
+    /// * A primary item, a secondary item, or an alias is declared twice.
+    /// * An option added by `add_option_ids()` uses an undeclared item ID.
+    /// * An item name given to `add_option_with()` contains `:`.
+    /// * An option colors a primary item that wasn't declared with
+    ///   `add_colored_primary_item()`.
     ///
     pub fn build(self) -> Result<Matrix<T>, BuildError> {
+        self.build_with_color_interner().map(|(matrix, _)| matrix)
+    }
+
+    /// Builds the matrix like `build()`, and also returns the color interner,
+    /// which now includes every color name used in the options.  Pass it to
+    /// `with_color_interner()` to keep the same color IDs in another matrix.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as `build()`.
+    pub fn build_with_color_interner(mut self) -> Result<(Matrix<T>, ColorInterner), BuildError> {
+        self.check()?;
+        let options = core::mem::take(&mut self.options);
+        let mut id_options = core::mem::take(&mut self.id_options).into_iter().peekable();
+        let mut colors = core::mem::take(&mut self.colors);
+        let header_names = self.header_names();
         let primary_items: &[String] = &self.primary_items;
         let secondary_items: &[String] = &self.secondary_items;
-        let options = self.options;
+        let required_items: &[String] = &self.required_items;
 
-        let header_names: HashMap<&str, ItemId> = primary_items
-            .iter()
-            .chain(secondary_items.iter())
-            .enumerate()
-            .map(|(i, name)| (name.as_ref(), ItemId::new(i)))
-            .collect();
+        // Build a list of all items (primary, then secondary, then required)
+        let mut matrix = Matrix::new(
+            self.primary_items.len(),
+            self.secondary_items.len() + self.required_items.len(),
+        );
+        matrix.set_secondary_mode(self.secondary_mode);
+        for i in 0..required_items.len() {
+            matrix.mark_required(ItemId::new(primary_items.len() + secondary_items.len() + i));
+        }
+        for name in &self.soft_items {
+            matrix.mark_soft(header_names[name.as_str()]);
+        }
+        matrix.reserve_options(options.len() + id_options.len());
+        // These buffers are reused from one option to the next, which saves
+        // two allocations per option on large problems.
+        let mut parsed_items = Vec::new();
+        let mut forbidden = Vec::new();
+        for (position, (meaning, opt_items)) in options.into_iter().enumerate() {
+            while let Some((_, meaning, items)) =
+                id_options.next_if(|(before, _, _)| *before == position)
+            {
+                matrix.add_option(meaning, &items);
+            }
+            parsed_items.clear();
+            forbidden.clear();
+            for item in &opt_items {
+                let item_id = header_names[item.name.as_str()];
+                match &item.color {
+                    ItemColor::None => parsed_items.push(ColoredItem::new(item_id)),
+                    ItemColor::Is(color) => {
+                        parsed_items.push(ColoredItem::with_color(item_id, colors.intern(color)));
+                    }
+                    ItemColor::IsNot(color) => forbidden.push((item_id, colors.intern(color))),
+                }
+            }
+            let option_id = OptionId::new(matrix.add_option(meaning, &parsed_items));
+            for &(item, color) in &forbidden {
+                matrix.forbid_color(option_id, item, color);
+            }
+        }
+        for (_, meaning, items) in id_options {
+            matrix.add_option(meaning, &items);
+        }
+        Ok((matrix, colors))
+    }
+}
 
-        let mut colors = HashMap::new();
-        for (_, option) in &options {
-            for item in option {
-                if let Some((_name, color)) = item.split_once(':') {
-                    let next_id = colors.len();
-                    colors.entry(color.to_string()).or_insert(next_id);
+/// An error returned by `Builder::from_csv()`.
+#[cfg(feature = "csv")]
+#[derive(Debug, thiserror::Error)]
+pub enum CsvError {
+    /// The input could not be read as CSV.
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    /// The input is empty, so there is no header row naming the items.
+    #[error("CSV input has no header row")]
+    NoHeader,
+    /// An option colors a primary item.
+    #[error("Primary item {item} is colored in row {row}")]
+    ColoredPrimaryItem {
+        /// The row number, counting the header as row 1.
+        row: usize,
+        /// The offending cell.
+        item: String,
+    },
+}
+
+#[cfg(feature = "csv")]
+impl Builder<String> {
+    /// Reads a problem from CSV, as exported from a spreadsheet.
+    ///
+    /// The first row names the primary items, then has a cell containing
+    /// just `|`, then names the secondary items.  Every other row is an
+    /// option, whose non-empty cells are the names of its items, with colors
+    /// written as `name:color`.  Rows with no items are skipped.
+    ///
+    /// If the first cell of the header row is empty, the first column holds
+    /// a label for each option, which becomes its meaning.  Otherwise, the
+    /// meaning is the option's row number, counting the header as row 1.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the input isn't valid CSV, has no header row, or
+    /// colors a primary item.  Undeclared items are reported by `build()`,
+    /// as usual.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::Builder;
+    ///
+    /// let input = "\
+    /// ,p,q,r,|,x,y
+    /// one,p,q,x,y:A
+    /// two,p,r,x:A,y
+    /// three,p,x:B
+    /// four,q,x:A
+    /// five,r,y:B
+    /// ";
+    /// let builder = Builder::from_csv(input.as_bytes()).unwrap();
+    /// let matrix = builder.build().unwrap();
+    /// let solution = matrix.solve_once().unwrap();
+    /// assert_eq!(solution.meanings(&matrix), ["four", "two"]);
+    /// ```
+    pub fn from_csv(reader: impl std::io::BufRead) -> Result<Self, CsvError> {
+        let mut csv = csv::ReaderBuilder::new()
+            .has_headers(false)
+            .flexible(true)
+            .trim(csv::Trim::All)
+            .from_reader(reader);
+        let mut records = csv.records();
+        let header = records.next().ok_or(CsvError::NoHeader)??;
+        let labeled = header.get(0) == Some("");
+
+        let mut builder = Self::new();
+        let mut secondary = false;
+        for name in header.iter().skip(usize::from(labeled)) {
+            match name {
+                "" => {}
+                "|" => secondary = true,
+                _ if secondary => builder.add_secondary_item(name),
+                _ => builder.add_primary_item(name),
+            }
+        }
+        for (i, record) in records.enumerate() {
+            let record = record?;
+            let row = i + 2;
+            let mut cells = record.iter();
+            let meaning = if labeled {
+                cells.next().unwrap_or_default().to_string()
+            } else {
+                row.to_string()
+            };
+            let items: Vec<String> = cells
+                .filter(|cell| !cell.is_empty())
+                .map(String::from)
+                .collect();
+            if items.is_empty() {
+                continue;
+            }
+            if let Some(item) = items.iter().find(|item| {
+                item.split_once(':')
+                    .is_some_and(|(name, _)| builder.primary_items.iter().any(|p| p == name))
+            }) {
+                return Err(CsvError::ColoredPrimaryItem {
+                    row,
+                    item: item.clone(),
+                });
+            }
+            builder.add_option_owned(meaning, items);
+        }
+        Ok(builder)
+    }
+}
+
+/// An item in an option, split into its name and color when the option is
+/// added.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+struct OptionItem {
+    name: String,
+    color: ItemColor,
+}
+
+/// The color part of an `OptionItem`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+enum ItemColor {
+    /// Written `x`.
+    None,
+    /// Written `x:red`.
+    Is(String),
+    /// Written `x:!red`: the option forbids the color.
+    IsNot(String),
+}
+
+impl OptionItem {
+    /// Splits an item written as in `add_option()` into its name and color.
+    fn parse(mut item: String) -> Self {
+        let (name, color) = split_item(&item);
+        let color = match color {
+            ItemColorName::None => ItemColor::None,
+            ItemColorName::Is(color) => ItemColor::Is(color.to_string()),
+            ItemColorName::IsNot(color) => ItemColor::IsNot(color.to_string()),
+        };
+        item.truncate(name.len());
+        OptionItem { name: item, color }
+    }
+}
+
+impl Display for OptionItem {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        match &self.color {
+            ItemColor::None => write!(f, "{}", self.name),
+            ItemColor::Is(color) => write!(f, "{}:{color}", self.name),
+            ItemColor::IsNot(color) => write!(f, "{}:!{color}", self.name),
+        }
+    }
+}
+
+/// Replaces each `{key}` in `template` that has an entry in `subst`, working
+/// left to right, so the values are never searched for placeholders.
+fn fill_template(template: &str, subst: &HashMap<&str, String>) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        let placeholder = &rest[start..];
+        let found = placeholder
+            .find('}')
+            .and_then(|end| Some((end, subst.get(&placeholder[1..end])?)));
+        if let Some((end, value)) = found {
+            filled.push_str(value);
+            rest = &placeholder[end + 1..];
+        } else {
+            filled.push('{');
+            rest = &placeholder[1..];
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+/// Returns the first name that appears more than once, if any.
+fn find_duplicate<'a>(names: impl Iterator<Item = &'a String>) -> Option<&'a str> {
+    let mut seen = HashSet::new();
+    names.map(String::as_str).find(|&name| !seen.insert(name))
+}
+
+/// The differences between two builders, as returned by `Builder::diff()`.
+/// "Added" means present in the other builder but not this one, and
+/// "removed" means the reverse.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuilderDiff<'a, T> {
+    /// Primary items that only the other builder declares.
+    pub added_primary_items: Vec<&'a str>,
+    /// Primary items that only this builder declares.
+    pub removed_primary_items: Vec<&'a str>,
+    /// Secondary items (including required ones) that only the other builder
+    /// declares.
+    pub added_secondary_items: Vec<&'a str>,
+    /// Secondary items (including required ones) that only this builder
+    /// declares.
+    pub removed_secondary_items: Vec<&'a str>,
+    /// Options that only the other builder has, as a meaning and item names.
+    pub added_options: Vec<(&'a T, Vec<String>)>,
+    /// Options that only this builder has, as a meaning and item names.
+    pub removed_options: Vec<(&'a T, Vec<String>)>,
+}
+
+impl<T> BuilderDiff<'_, T> {
+    /// Returns `true` if the two builders describe the same problem.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.added_primary_items.is_empty()
+            && self.removed_primary_items.is_empty()
+            && self.added_secondary_items.is_empty()
+            && self.removed_secondary_items.is_empty()
+            && self.added_options.is_empty()
+            && self.removed_options.is_empty()
+    }
+}
+
+impl<T: PartialEq> Builder<T> {
+    /// Compares this builder with `other`, to check that a change to a
+    /// generator didn't change the problem it produces.
+    ///
+    /// Items are compared by name, ignoring the order they were declared in.
+    /// Options are compared by meaning and by their set of items, so the
+    /// order of the options, and of the items within an option, doesn't
+    /// matter either.  An option that appears twice in one builder and once
+    /// in the other counts as one difference.  Options added with
+    /// `add_option_ids()` aren't compared.
+    ///
+    /// # Example
+    /// ```
+    /// use xcc::Matrix;
+    ///
+    /// let mut old = Matrix::builder();
+    /// old.add_primary_items(["a", "b"]);
+    /// old.add_option(1, ["a", "b"]);
+    ///
+    /// let mut new = Matrix::builder();
+    /// new.add_primary_items(["b", "a"]);
+    /// new.add_option(1, ["b", "a"]);
+    /// assert!(old.diff(&new).is_empty());
+    ///
+    /// new.add_option(2, ["a"]);
+    /// let diff = old.diff(&new);
+    /// assert_eq!(diff.added_options, [(&2, vec!["a".to_string()])]);
+    /// assert!(diff.removed_options.is_empty());
+    /// ```
+    #[must_use]
+    pub fn diff<'a>(&'a self, other: &'a Builder<T>) -> BuilderDiff<'a, T> {
+        let secondary = |b: &'a Builder<T>| -> Vec<&'a String> {
+            b.secondary_items.iter().chain(&b.required_items).collect()
+        };
+        let (ours, theirs) = (secondary(self), secondary(other));
+        BuilderDiff {
+            added_primary_items: names_missing_from(&other.primary_items, &self.primary_items),
+            removed_primary_items: names_missing_from(&self.primary_items, &other.primary_items),
+            added_secondary_items: names_missing_from(theirs.iter().copied(), ours.iter().copied()),
+            removed_secondary_items: names_missing_from(
+                ours.iter().copied(),
+                theirs.iter().copied(),
+            ),
+            added_options: options_missing_from(&other.options, &self.options),
+            removed_options: options_missing_from(&self.options, &other.options),
+        }
+    }
+}
+
+/// Returns the names in `names` that aren't in `others`.
+fn names_missing_from<'a>(
+    names: impl IntoIterator<Item = &'a String>,
+    others: impl IntoIterator<Item = &'a String>,
+) -> Vec<&'a str> {
+    let others: HashSet<&str> = others.into_iter().map(String::as_str).collect();
+    names
+        .into_iter()
+        .map(String::as_str)
+        .filter(|name| !others.contains(name))
+        .collect()
+}
+
+/// Returns the options in `options` that have no match in `others`, pairing
+/// each match off so that repeated options are counted properly.
+fn options_missing_from<'a, T: PartialEq>(
+    options: &'a [(T, Vec<OptionItem>)],
+    others: &'a [(T, Vec<OptionItem>)],
+) -> Vec<(&'a T, Vec<String>)> {
+    let sorted = |items: &'a [OptionItem]| {
+        let mut items: Vec<&OptionItem> = items.iter().collect();
+        items.sort_unstable();
+        items
+    };
+    // Meanings can only be compared for equality, so group the options by
+    // their items, and compare meanings within a group.
+    let mut unmatched: HashMap<Vec<&OptionItem>, Vec<&T>> = HashMap::new();
+    for (meaning, items) in others {
+        unmatched.entry(sorted(items)).or_default().push(meaning);
+    }
+    let mut missing = Vec::new();
+    for (meaning, items) in options {
+        let matched = unmatched.get_mut(&sorted(items)).and_then(|meanings| {
+            let pos = meanings.iter().position(|m| *m == meaning)?;
+            Some(meanings.swap_remove(pos))
+        });
+        if matched.is_none() {
+            missing.push((meaning, items.iter().map(ToString::to_string).collect()));
+        }
+    }
+    missing
+}
+
+impl<T: Default> Builder<T> {
+    /// Requires exactly `k` of the primary items in `group_items` to be
+    /// covered by your own options.  The rest are covered by auxiliary
+    /// "slack" options.
+    ///
+    /// The group items must be declared as primary items in the usual way.
+    /// This adds a chain of auxiliary items, one step per group item, where
+    /// each step either lets the item through or covers it with slack, and a
+    /// colored secondary item carries the running count of slack from one
+    /// step to the next.  Each way of choosing the `k` items therefore gives
+    /// exactly one solution.
+    ///
+    /// The auxiliary options have the meaning `T::default()`, so you will see
+    /// them in `Solution::meanings()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `k` is larger than the number of group items.
+    ///
+    /// # Example
+    /// ```
+    /// use xcc::Matrix;
+    ///
+    /// // Choose exactly 2 of 4 shifts.
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["mon", "tue", "wed", "thu"]);
+    /// for day in ["mon", "tue", "wed", "thu"] {
+    ///     builder.add_option(day, [day]);
+    /// }
+    /// builder.add_exactly_k(&["mon", "tue", "wed", "thu"], 2);
+    /// let matrix = builder.build().unwrap();
+    ///
+    /// let solutions = matrix.solve_all();
+    /// assert_eq!(solutions.len(), 6);
+    /// for solution in &solutions {
+    ///     let shifts = solution.meanings(&matrix).into_iter().filter(|m| !m.is_empty());
+    ///     assert_eq!(shifts.count(), 2);
+    /// }
+    /// ```
+    pub fn add_exactly_k(&mut self, group_items: &[&str], k: usize) {
+        let n = group_items.len();
+        assert!(k <= n, "cannot choose {k} of {n} items");
+        let group = self.num_aux_groups;
+        self.num_aux_groups += 1;
+
+        let step = |i: usize| format!("#exactly{group}.step{i}");
+        let count = |i: usize| format!("#exactly{group}.count{i}");
+        // The number of slack options among the first i items.
+        let min_slack = |i: usize| i.saturating_sub(k);
+        let max_slack = |i: usize| i.min(n - k);
+
+        self.add_primary_items_owned((1..=n).map(step).collect());
+        self.add_secondary_items_owned((1..n).map(count).collect());
+        for (i, &item) in (1..=n).zip(group_items) {
+            for before in min_slack(i - 1)..=max_slack(i - 1) {
+                for slack in [false, true] {
+                    let after = before + usize::from(slack);
+                    if !(min_slack(i)..=max_slack(i)).contains(&after) {
+                        continue;
+                    }
+                    let mut items = vec![step(i)];
+                    if slack {
+                        items.push(item.to_string());
+                    }
+                    if i > 1 {
+                        items.push(format!("{}:{before}", count(i - 1)));
+                    }
+                    if i < n {
+                        items.push(format!("{}:{after}", count(i)));
+                    }
+                    self.add_option_owned(T::default(), items);
                 }
             }
         }
+    }
+}
 
-        // Build a list of all items (primary, then secondary)
-        let mut matrix = Matrix::new(self.primary_items.len(), self.secondary_items.len());
-        for (meaning, opt_items) in options {
-            let mut parsed_items = Vec::new();
+impl<T: From<(String, String)>> Builder<T> {
+    /// Requires the `cells` to take different values from `values`, as in
+    /// the rows, columns and boxes of a Sudoku.
+    ///
+    /// The first time a cell appears in a group, it's declared as a primary
+    /// item, unless it already has been, and gets one option for each value,
+    /// with the meaning `T::from((cell, value))`.  Covering the cell means
+    /// giving it a value.  Each group also gets an item for each of its
+    /// values, named `#alldiff{n}.{value}` for the `n`th auxiliary group,
+    /// and each option that gives one of its cells that value includes the
+    /// item.  When there are as many values as cells, every value has to be
+    /// used, so the value items are primary; otherwise they're secondary.
+    ///
+    /// A group may give a cell values it didn't have before, but those
+    /// values never get options, so the cell can't take them.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BuildError::ValueNotInGroup` if a cell that's already in a
+    /// group can take a value that's missing from `values`.  The builder is
+    /// left unchanged.
+    ///
+    /// # Example
+    /// ```
+    /// use xcc::{Builder, Matrix};
+    ///
+    /// // A 4x4 Sudoku.
+    /// let mut builder: Builder<(String, String)> = Matrix::builder();
+    /// let values = ["1", "2", "3", "4"];
+    /// let cell = |row: usize, col: usize| format!("r{row}c{col}");
+    /// for i in 0..4 {
+    ///     let row: Vec<_> = (0..4).map(|col| cell(i, col)).collect();
+    ///     let col: Vec<_> = (0..4).map(|row| cell(row, i)).collect();
+    ///     let boxed: Vec<_> = (0..4).map(|j| cell(i / 2 * 2 + j / 2, i % 2 * 2 + j % 2)).collect();
+    ///     for group in [row, col, boxed] {
+    ///         let group: Vec<&str> = group.iter().map(String::as_str).collect();
+    ///         builder.add_all_different(&group, &values).unwrap();
+    ///     }
+    /// }
+    /// let matrix = builder.build().unwrap();
+    /// assert_eq!(matrix.num_options(), 64);
+    /// assert_eq!(matrix.solve_all().len(), 288);
+    /// ```
+    pub fn add_all_different(&mut self, cells: &[&str], values: &[&str]) -> Result<(), BuildError> {
+        // Check every cell before changing anything, so an error leaves the
+        // builder as it was.
+        for &cell in cells {
+            let mut assigned = self.assignments.get(cell).into_iter().flatten();
+            if assigned.any(|(value, _)| !values.contains(&value.as_str())) {
+                return Err(BuildError::ValueNotInGroup(cell.to_string()));
+            }
+        }
+        let group = self.num_aux_groups;
+        self.num_aux_groups += 1;
+        let value_item = |value: &str| format!("#alldiff{group}.{value}");
 
-            for s in opt_items {
-                let parsed_item = if let Some((name, color)) = s.split_once(':') {
-                    let item_id = *header_names
-                        .get(name)
-                        .ok_or_else(|| BuildError::ItemNotDeclared(name.to_string()))?;
-                    let color_id = colors[color];
-                    ColoredItem::with_color(item_id, Color::new(color_id))
-                } else {
-                    let item_id = header_names[s.as_str()];
-                    ColoredItem::new(item_id)
-                };
-                parsed_items.push(parsed_item);
+        let value_items = values.iter().map(|value| value_item(value)).collect();
+        if cells.len() == values.len() {
+            self.add_primary_items_owned(value_items);
+        } else {
+            self.add_secondary_items_owned(value_items);
+        }
+        for &cell in cells {
+            if !self.assignments.contains_key(cell) {
+                if !self.primary_items.iter().any(|item| item == cell) {
+                    self.add_primary_item(cell);
+                }
+                let mut options = Vec::with_capacity(values.len());
+                for &value in values {
+                    options.push((value.to_string(), self.options.len()));
+                    let meaning = T::from((cell.to_string(), value.to_string()));
+                    self.add_option_owned(meaning, vec![cell.to_string()]);
+                }
+                self.assignments.insert(cell.to_string(), options);
+            }
+            for (value, option) in &self.assignments[cell] {
+                self.options[*option].1.push(OptionItem {
+                    name: value_item(value),
+                    color: ItemColor::None,
+                });
             }
-            matrix.add_option(meaning, &parsed_items);
         }
-        Ok(matrix)
+        Ok(())
     }
 }
 
@@ -184,16 +1562,373 @@ impl<T: Debug> Builder<T> {
     /// Only available if the type of meanings is Debug.
     #[must_use]
     pub fn dump_knuth_format(&self) -> String {
-        let mut buf = self.primary_items.join(" ").to_string();
-        if !self.secondary_items.is_empty() {
-            buf.push_str(" | ");
-            buf.push_str(&self.secondary_items.join(" "));
-        }
-        buf.push('\n');
-        for (_, items) in &self.options {
-            buf.push_str(&items.join(" "));
+        let mut buf = String::new();
+        for line in self.knuth_format_lines() {
+            buf.push_str(&line);
             buf.push('\n');
         }
         buf
     }
+
+    /// Writes the same output as `dump_knuth_format()` to `w`, one line at a
+    /// time, so that a large matrix never has to be held in memory as a
+    /// single string.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from writing to `w`.
+    ///
+    /// # Example
+    /// ```
+    /// use xcc::Matrix;
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["a", "b"]);
+    /// builder.add_option(1, ["a", "b"]);
+    /// let mut out = Vec::new();
+    /// builder.write_knuth_format(&mut out).unwrap();
+    /// assert_eq!(String::from_utf8(out).unwrap(), builder.dump_knuth_format());
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn write_knuth_format<W: std::io::Write>(&self, w: &mut W) -> std::io::Result<()> {
+        for line in self.knuth_format_lines() {
+            writeln!(w, "{line}")?;
+        }
+        Ok(())
+    }
+
+    /// Returns the lines of the dlx2 format: the item declarations, then one
+    /// line per option.
+    fn knuth_format_lines(&self) -> impl Iterator<Item = String> + '_ {
+        let mut header = self.primary_items.join(" ");
+        if !self.secondary_items.is_empty() || !self.required_items.is_empty() {
+            header.push_str(" |");
+            for item in self.secondary_items.iter().chain(&self.required_items) {
+                header.push(' ');
+                header.push_str(item);
+            }
+        }
+        core::iter::once(header).chain(self.options.iter().map(|(_, items)| {
+            let items: Vec<String> = items.iter().map(ToString::to_string).collect();
+            items.join(" ")
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_duplicate_primary_item() {
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["a", "b", "a"]);
+        builder.add_option(1, ["a", "b"]);
+        let err = builder.build().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DuplicatePrimaryItem);
+        assert_eq!(err.item_name(), Some("a"));
+    }
+
+    #[test]
+    fn test_item_declared_primary_and_secondary() {
+        let mut builder = Matrix::builder();
+        builder.add_primary_item("a");
+        builder.add_secondary_item("a");
+        builder.add_option(1, ["a"]);
+        let err = builder.build().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ItemDeclaredTwice);
+        assert_eq!(err.item_name(), Some("a"));
+    }
+
+    #[test]
+    fn test_colored_primary_item() {
+        let mut builder = Matrix::builder();
+        builder.add_colored_primary_item("cell");
+        builder.add_primary_item("other");
+        builder.add_option("red", ["cell:red", "other"]);
+        builder.add_option("blue", ["cell:blue"]);
+        builder.add_option("other", ["other"]);
+        let matrix = builder.clone().build().unwrap();
+        let mut meanings = matrix.solve_all_meanings();
+        meanings.sort();
+        assert_eq!(meanings, [vec![&"blue", &"other"], vec![&"red"]]);
+
+        // The item can't also have secondary uses for its color to agree
+        // with.
+        builder.add_secondary_item("cell");
+        let err = builder.build().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ItemDeclaredTwice);
+        assert_eq!(err.item_name(), Some("cell"));
+    }
+
+    #[test]
+    fn test_duplicate_secondary_item() {
+        let mut builder = Matrix::builder();
+        builder.add_primary_item("a");
+        builder.add_secondary_items(["x", "y"]);
+        builder.add_secondary_item("x");
+        builder.add_option(1, ["a", "x"]);
+        let err = builder.build().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DuplicateSecondaryItem);
+        assert_eq!(err.item_name(), Some("x"));
+    }
+
+    #[test]
+    fn test_undeclared_uncolored_item() {
+        let mut builder = Matrix::builder();
+        builder.add_primary_item("a");
+        builder.add_option(1, ["a", "b"]);
+        let err = builder.build().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ItemNotDeclared);
+        assert_eq!(err.item_name(), Some("b"));
+    }
+
+    #[test]
+    fn test_exactly_k() {
+        let items = ["a", "b", "c", "d", "e"];
+        for k in 0..=items.len() {
+            let mut builder = Matrix::builder();
+            builder.add_primary_items(items);
+            for item in items {
+                builder.add_option(1, [item]);
+            }
+            builder.add_exactly_k(&items, k);
+            let matrix = builder.build().unwrap();
+
+            let solutions = matrix.solve_all();
+            let expected = [1, 5, 10, 10, 5, 1][k];
+            assert_eq!(solutions.len(), expected, "k = {k}");
+            for solution in &solutions {
+                let chosen = solution.meanings(&matrix).into_iter().filter(|&&m| m == 1);
+                assert_eq!(chosen.count(), k);
+            }
+        }
+    }
+
+    #[test]
+    fn test_two_exactly_k_groups() {
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["a", "b", "c", "d"]);
+        builder.add_option(1, ["a", "c"]);
+        builder.add_option(1, ["b", "d"]);
+        builder.add_option(1, ["a", "d"]);
+        builder.add_exactly_k(&["a", "b"], 1);
+        builder.add_exactly_k(&["c", "d"], 1);
+        let matrix = builder.build().unwrap();
+        assert_eq!(matrix.solve_all().len(), 3);
+    }
+
+    #[test]
+    fn test_alias_errors() {
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["a", "b"]);
+        builder.add_alias("x", "c");
+        builder.add_option(1, ["a", "b"]);
+        let err = builder.build().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AliasTargetNotDeclared);
+        assert_eq!(err.item_name(), Some("c"));
+
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["a", "b"]);
+        builder.add_alias("b", "a");
+        builder.add_option(1, ["a", "b"]);
+        let err = builder.build().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::AliasShadowsItem);
+        assert_eq!(err.item_name(), Some("b"));
+
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["a", "b"]);
+        builder.add_alias("x", "a");
+        builder.add_alias("x", "b");
+        builder.add_option(1, ["x", "b"]);
+        let err = builder.build().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::DuplicateAlias);
+        assert_eq!(err.item_name(), Some("x"));
+
+        // An alias doesn't make a primary item colorable.
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["a", "b"]);
+        builder.add_alias("x", "a");
+        builder.add_option(1, ["x:red", "b"]);
+        let err = builder.build().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PrimaryItemColored);
+        assert_eq!(err.item_name(), Some("a"));
+    }
+
+    #[test]
+    fn test_colored_alias() {
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["a", "b"]);
+        builder.add_secondary_item("x");
+        builder.add_alias("y", "x");
+        builder.add_option(1, ["a", "x:red"]);
+        builder.add_option(2, ["b", "y:blue"]);
+        builder.add_option(3, ["b", "y:red"]);
+        let matrix = builder.build().unwrap();
+        let solutions = matrix.solve_all();
+        assert_eq!(solutions.len(), 1);
+        let mut meanings = solutions[0].meanings(&matrix);
+        meanings.sort();
+        assert_eq!(meanings, [&1, &3]);
+    }
+
+    #[cfg(feature = "csv")]
+    #[test]
+    fn test_from_csv_without_labels() {
+        let input = "a,b,|,x\na,x:1\n,,\nb,x:1\n";
+        let matrix = Builder::from_csv(input.as_bytes())
+            .unwrap()
+            .build()
+            .unwrap();
+        let solution = matrix.solve_once().unwrap();
+        assert_eq!(solution.meanings(&matrix), ["2", "4"]);
+
+        let input = "a,|,x\na:red,x\n";
+        let err = Builder::from_csv(input.as_bytes()).unwrap_err();
+        assert!(matches!(
+            err,
+            CsvError::ColoredPrimaryItem { row: 2, ref item } if item == "a:red"
+        ));
+    }
+
+    #[test]
+    fn test_template_values_are_not_substituted_again() {
+        let subst = HashMap::from([("a", "{b}".to_string()), ("b", "x".to_string())]);
+        assert_eq!(fill_template("{a}{b}", &subst), "{b}x");
+        assert_eq!(fill_template("{b}{a}", &subst), "x{b}");
+        assert_eq!(fill_template("{{b}} {c} {", &subst), "{x} {c} {");
+    }
+
+    #[test]
+    fn test_option_with_keeps_names_and_colors_apart() {
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["a", "b"]);
+        builder.add_secondary_item("x");
+        builder.add_option_with(1, [("a", None), ("x", Some("!red"))]);
+        builder.add_option(2, ["b", "x"]);
+        let matrix = builder.clone().build().unwrap();
+        // `!red` is an ordinary color here, not a forbidden one, so the
+        // first option uses x and clashes with the second.
+        assert!(matrix.solve_all().is_empty());
+
+        builder.add_option_with(3, [("a:b", None::<&str>)]);
+        let err = builder.build().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::InvalidItemName);
+        assert_eq!(err.item_name(), Some("a:b"));
+    }
+
+    #[test]
+    fn test_channeled_item_colored_on_every_channel() {
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["a", "b", "c"]);
+        builder.add_channeled_item("x", &["1", "2"]);
+        builder.add_option(1, ["a", "x:red"]);
+        builder.add_option(2, ["b", "x/1:red"]);
+        builder.add_option(3, ["c", "x/2:blue"]);
+        builder.add_option(4, ["c"]);
+        assert!(builder
+            .dump_knuth_format()
+            .ends_with("a x/1:red x/2:red\nb x/1:red\nc x/2:blue\nc\n"));
+        let matrix = builder.build().unwrap();
+        let solutions = matrix.solve_all();
+        assert_eq!(solutions.len(), 1);
+        let mut meanings = solutions[0].meanings(&matrix);
+        meanings.sort();
+        assert_eq!(meanings, [&1, &2, &4]);
+    }
+
+    #[test]
+    fn test_option_ids_keep_their_place() {
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["a", "b", "c"]);
+        builder.add_option_ids(0, &[ColoredItem::new(ItemId::new(0))]);
+        builder.add_option(1, ["b"]);
+        builder.add_option_ids(2, &[ColoredItem::new(ItemId::new(2))]);
+        builder.add_option_ids(3, &[ColoredItem::new(ItemId::new(1))]);
+        builder.add_option(4, ["a"]);
+        builder.add_option_ids(5, &[ColoredItem::new(ItemId::new(2))]);
+        let matrix = builder.build().unwrap();
+        for i in 0..6 {
+            assert_eq!(matrix.meaning(i), &i);
+        }
+    }
+
+    #[test]
+    fn test_option_ids_cannot_color_primary_items() {
+        let red = crate::Color::new(0);
+        let mut builder = Matrix::builder();
+        builder.add_primary_item("a");
+        builder.add_colored_primary_item("b");
+        builder.add_option_ids(1, &[ColoredItem::with_color(ItemId::new(1), red)]);
+        builder.add_option(2, ["a"]);
+        assert!(builder.check().is_ok());
+
+        builder.add_option_ids(3, &[ColoredItem::with_color(ItemId::new(0), red)]);
+        let err = builder.build().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PrimaryItemColored);
+        assert_eq!(err.item_name(), Some("a"));
+    }
+
+    #[test]
+    fn test_primary_item_declared_after_a_colored_use() {
+        let mut builder = Matrix::builder();
+        builder.add_option(1, ["a:red"]);
+        builder.add_primary_item("a");
+        let err = builder.build().unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::PrimaryItemColored);
+    }
+
+    #[test]
+    fn test_diff_pairs_off_repeated_options() {
+        let mut old = Matrix::builder();
+        old.add_primary_items(["a", "b"]);
+        old.add_secondary_item("x");
+        old.add_option(1, ["a", "x:red"]);
+        old.add_option(1, ["a", "x:red"]);
+        old.add_option(2, ["a", "x:red"]);
+        old.add_option(3, ["b"]);
+
+        let mut new = Matrix::builder();
+        new.add_primary_items(["a", "b"]);
+        new.add_secondary_item("x");
+        new.add_option(2, ["x:red", "a"]);
+        new.add_option(1, ["x:red", "a"]);
+        new.add_option(3, ["b", "x:!red"]);
+
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff.added_options,
+            [(&3, vec!["b".to_string(), "x:!red".to_string()])]
+        );
+        assert_eq!(
+            diff.removed_options,
+            [
+                (&1, vec!["a".to_string(), "x:red".to_string()]),
+                (&3, vec!["b".to_string()])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_all_different_groups_must_cover_each_cells_values() {
+        let mut builder: Builder<(String, String)> = Matrix::builder();
+        builder
+            .add_all_different(&["a", "b"], &["1", "2", "3"])
+            .unwrap();
+
+        // A later group can offer values the cell doesn't have.
+        builder
+            .add_all_different(&["a", "c"], &["1", "2", "3", "4"])
+            .unwrap();
+
+        // But it can't leave out any that it does have, and nothing changes.
+        let before = builder.clone();
+        let err = builder
+            .add_all_different(&["b", "d"], &["1", "2"])
+            .unwrap_err();
+        assert_eq!(err.kind(), ErrorKind::ValueNotInGroup);
+        assert_eq!(err.item_name(), Some("b"));
+        assert!(before.diff(&builder).is_empty());
+        assert_eq!(before.num_aux_groups, builder.num_aux_groups);
+    }
 }
@@ -0,0 +1,8 @@
+//! The map and set types used in the crate's API.
+//!
+//! These are the `hashbrown` collections, which work the same way with or
+//! without the `std` feature, so turning `std` off doesn't change any types.
+//! They have the same interface as `std::collections::HashMap` and
+//! `HashSet`.
+
+pub use hashbrown::{HashMap, HashSet};
@@ -0,0 +1,130 @@
+//! A `Builder` for puzzles played on a rectangular grid.
+
+use super::{BuildError, Builder, Matrix};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// A `Builder` that knows about a grid of `rows` x `cols` cells, and takes
+/// care of naming the cells and finding their neighbors.
+///
+/// Cell `(r, c)` is row `r` and column `c`, counting from 0 at the top left.
+/// Use `builder_mut()` to add any other items and the options; the cell
+/// names from `cell_item()` can be used in them like any other item name.
+///
+/// # Example
+///
+/// ```
+/// use xcc::GridBuilder;
+///
+/// // Tile a 2x2 grid with dominoes.
+/// let mut grid = GridBuilder::new(2, 2);
+/// grid.add_primary_cells();
+/// for (r, c) in grid.cells() {
+///     for (r2, c2) in grid.neighbors(r, c) {
+///         if (r2, c2) > (r, c) {
+///             let items = [grid.cell_item(r, c), grid.cell_item(r2, c2)];
+///             grid.builder_mut().add_option(((r, c), (r2, c2)), items);
+///         }
+///     }
+/// }
+/// let matrix = grid.build().unwrap();
+/// assert_eq!(matrix.solve_all().len(), 2);
+/// ```
+#[derive(Debug, Clone)]
+pub struct GridBuilder<T> {
+    rows: usize,
+    cols: usize,
+    builder: Builder<T>,
+}
+
+impl<T> GridBuilder<T> {
+    /// Creates a builder for a grid with the given number of rows and
+    /// columns.  No items are declared yet.
+    #[must_use]
+    pub fn new(rows: usize, cols: usize) -> Self {
+        GridBuilder {
+            rows,
+            cols,
+            builder: Builder::new(),
+        }
+    }
+
+    /// Returns the number of rows in the grid.
+    #[must_use]
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    /// Returns the number of columns in the grid.
+    #[must_use]
+    pub fn cols(&self) -> usize {
+        self.cols
+    }
+
+    /// Returns the item name for the cell at row `r`, column `c`.
+    #[must_use]
+    pub fn cell_item(&self, r: usize, c: usize) -> String {
+        format!("{r},{c}")
+    }
+
+    /// Returns the coordinates of every cell, row by row.
+    pub fn cells(&self) -> impl Iterator<Item = (usize, usize)> {
+        let cols = self.cols;
+        (0..self.rows).flat_map(move |r| (0..cols).map(move |c| (r, c)))
+    }
+
+    /// Returns the cells above, to the left of, to the right of, and below
+    /// `(r, c)`, leaving out any that would be off the edge of the grid.
+    #[must_use]
+    pub fn neighbors(&self, r: usize, c: usize) -> Vec<(usize, usize)> {
+        let mut neighbors = Vec::with_capacity(4);
+        if r > 0 {
+            neighbors.push((r - 1, c));
+        }
+        if c > 0 {
+            neighbors.push((r, c - 1));
+        }
+        if c + 1 < self.cols {
+            neighbors.push((r, c + 1));
+        }
+        if r + 1 < self.rows {
+            neighbors.push((r + 1, c));
+        }
+        neighbors
+    }
+
+    /// Declares every cell as a primary item, so each must be covered
+    /// exactly once.
+    pub fn add_primary_cells(&mut self) {
+        let names = self.cells().map(|(r, c)| self.cell_item(r, c)).collect();
+        self.builder.add_primary_items_owned(names);
+    }
+
+    /// Declares every cell as a secondary item, typically so it can be
+    /// colored with the cell's contents.
+    pub fn add_secondary_cells(&mut self) {
+        let names = self.cells().map(|(r, c)| self.cell_item(r, c)).collect();
+        self.builder.add_secondary_items_owned(names);
+    }
+
+    /// Returns the underlying `Builder`, for adding other items and options.
+    pub fn builder_mut(&mut self) -> &mut Builder<T> {
+        &mut self.builder
+    }
+
+    /// Returns the underlying `Builder`.
+    #[must_use]
+    pub fn into_builder(self) -> Builder<T> {
+        self.builder
+    }
+
+    /// Builds the matrix.  See `Builder::build()`.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error from `Builder::build()`.
+    pub fn build(self) -> Result<Matrix<T>, BuildError> {
+        self.builder.build()
+    }
+}
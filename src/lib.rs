@@ -1,3 +1,4 @@
+#![cfg_attr(not(feature = "std"), no_std)]
 #![warn(clippy::pedantic)]
 #![warn(missing_docs)]
 //! Colored Exact Cover Solver
@@ -33,18 +34,51 @@
 //!
 //! There are many examples in the `examples` directory.
 //!
+//! ## Optional features
+//!
+//! - `std` (on by default): use the standard library.  Without it, the crate
+//!   is `no_std` and only needs `alloc`.  The API is the same either way:
+//!   maps and sets are always the ones in [`collections`].
+//! - `rayon`: parallel solving with `Matrix::solve_unique_batch()`,
+//!   `Matrix::solve_unique_batch_timed()`, and
+//!   `Matrix::solve_parallel_channel()`.
+//! - `serde`: `Serialize` and `Deserialize` for `SavedState` and the ID types,
+//!   so that a search can be checkpointed and resumed later.
+//! - `csv`: `Builder::from_csv()`, for problems laid out in a spreadsheet.
+//!
+
+extern crate alloc;
 
 mod builder;
+pub mod collections;
+mod grid;
 mod matrix;
+mod policy;
+mod problem;
 pub mod samples;
 mod solver;
 mod types;
 mod unique;
 
-pub use self::builder::Builder;
-pub use self::matrix::Matrix;
+#[cfg(feature = "csv")]
+pub use self::builder::CsvError;
+pub use self::builder::{BuildError, Builder, BuilderDiff, ErrorKind};
+pub use self::grid::GridBuilder;
+pub use self::matrix::{Matrix, MatrixStats};
+pub use self::policy::{ColorPolicy, Commit, DefaultColorPolicy};
+pub use self::problem::Problem;
 pub use self::solver::Solution;
-pub use self::solver::{Limit, Solver};
+pub use self::solver::{
+    Agreement, Assignment, Conflict, Limit, OptionStatus, SavedState, SearchTree,
+    SolutionsWithColors, SolveError, Solver, Timeout, UniquenessChecker,
+};
 pub use self::types::ColoredItem;
-pub use self::types::{Color, ItemId};
+pub use self::types::{
+    colors_compatible, Color, ColorInterner, ItemId, OptionId, Permutation, SecondaryMode,
+};
 pub use self::unique::Unique;
+
+/// The bitset type used by `SavedState` and `Solution::to_bitset()`,
+/// re-exported so callers don't need their own matching `fixedbitset`
+/// dependency.
+pub use fixedbitset::FixedBitSet;
@@ -1,10 +1,15 @@
 use super::Solution;
-use crate::types::{Color, ItemId, OptionId};
-use crate::Builder;
+use crate::collections::{HashMap, HashSet};
+use crate::types::{colors_compatible, Color, ItemId, OptionId, Permutation, SecondaryMode};
 use crate::ColoredItem;
 use crate::Unique;
+use crate::{BuildError, Builder, Problem};
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{Display, Write};
 use fixedbitset::FixedBitSet;
-use std::collections::HashMap;
 
 /// A compiled specification of an exact cover problem with colored items.
 ///
@@ -17,10 +22,37 @@ use std::collections::HashMap;
 /// let matrix = builder.build();
 /// ```
 ///
-#[derive(Debug)]
+/// # Equality and hashing
+///
+/// Two matrices are equal if they have the same items, of the same kinds,
+/// and the same options, with the same items, colors, and meanings, in the
+/// same order.  Matrices hash consistently with this, so they can be used as
+/// keys to cache solutions:
+///
+/// ```
+/// use std::collections::HashMap;
+/// use xcc::samples::toy;
+///
+/// let mut cache = HashMap::new();
+/// cache.insert(toy(), toy().solve_all());
+/// assert_eq!(cache[&toy()].len(), 1);
+/// ```
+#[derive(Debug, PartialEq, Eq, Hash)]
 pub struct Matrix<T> {
     num_items: usize,
     num_primary_items: usize,
+    /// Bitmask of the items that are primary.
+    primary_items: FixedBitSet,
+    /// Bitmask of the secondary items that must nevertheless be covered.
+    required_items: FixedBitSet,
+    /// Bitmask of the secondary items that solutions should cover if they
+    /// can.
+    soft_items: FixedBitSet,
+    /// How uncolored secondary items are treated.
+    secondary_mode: SecondaryMode,
+    /// The options that forbid each (item, color) pair, so the solver can
+    /// find them without scanning every option.
+    forbidding: BTreeMap<(ItemId, Color), Vec<OptionId>>,
     options: Vec<OptionData<T>>,
 }
 
@@ -50,22 +82,152 @@ impl<T> Matrix<T> {
         self.num_primary_items
     }
 
+    /// Returns `true` if the given item is a primary item.
+    #[must_use]
+    pub fn is_primary(&self, item: ItemId) -> bool {
+        self.primary_items.contains(item.index())
+    }
+
+    /// Returns `true` if the given item is a secondary item that must be
+    /// covered, as declared with `Builder::add_required_colored_item()`.
+    #[must_use]
+    pub fn is_required(&self, item: ItemId) -> bool {
+        self.required_items.contains(item.index())
+    }
+
+    /// Marks a secondary item as required: every solution must contain at
+    /// least one option that uses it, and all such options must agree on its
+    /// color.  See `Builder::add_required_colored_item()`.
+    pub fn mark_required(&mut self, item: ItemId) {
+        self.required_items.insert(item.index());
+    }
+
+    /// Returns `true` if the given item is a soft primary item, as declared
+    /// with `Builder::add_soft_primary_item()`.
+    #[must_use]
+    pub fn is_soft(&self, item: ItemId) -> bool {
+        self.soft_items.contains(item.index())
+    }
+
+    /// Marks a secondary item as soft: it can be used at most once, like any
+    /// uncolored secondary item, and `Solver::solve_max_soft()` looks for a
+    /// solution that covers as many soft items as possible.  See
+    /// `Builder::add_soft_primary_item()`.
+    pub fn mark_soft(&mut self, item: ItemId) {
+        self.soft_items.insert(item.index());
+    }
+
+    /// Returns how uncolored secondary items are treated.  See
+    /// `SecondaryMode`.
+    #[must_use]
+    pub fn secondary_mode(&self) -> SecondaryMode {
+        self.secondary_mode
+    }
+
+    /// Sets how uncolored secondary items are treated.  See
+    /// `Builder::secondary_mode()`.
+    pub fn set_secondary_mode(&mut self, mode: SecondaryMode) {
+        self.secondary_mode = mode;
+    }
+
+    /// Returns a summary of the size and density of the matrix.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let matrix = xcc::samples::toy();
+    /// let stats = matrix.stats();
+    /// assert_eq!(stats.num_primary, 3);
+    /// assert_eq!(stats.num_secondary, 2);
+    /// assert_eq!(stats.num_options, 5);
+    /// assert_eq!(stats.total_item_uses, 14);
+    /// assert_eq!(stats.num_colors, 2);
+    /// ```
+    #[must_use]
+    pub fn stats(&self) -> MatrixStats {
+        let colors: HashSet<Color> = self
+            .options
+            .iter()
+            .flat_map(|option| option.colors.iter().flatten().copied())
+            .collect();
+        MatrixStats {
+            num_primary: self.num_primary_items,
+            num_secondary: self.num_items - self.num_primary_items,
+            num_options: self.options.len(),
+            total_item_uses: self
+                .options
+                .iter()
+                .map(|option| option.items.count_ones(..))
+                .sum(),
+            num_colors: colors.len(),
+        }
+    }
+
     /// Solves the exact cover problem represented by this matrix, returning all
     /// solutions.
     ///
     /// # Example
     ///
     /// ```
-    /// let mut matrix = xcc::samples::toy();
+    /// let matrix = xcc::samples::toy();
     /// let solutions = matrix.solve_all();
     /// assert_eq!(solutions.len(), 1);
     /// assert_eq!(solutions[0].meanings(&matrix), [&4, &2]);
     /// ```
-    pub fn solve_all(&mut self) -> Vec<Solution> {
+    #[must_use]
+    pub fn solve_all(&self) -> Vec<Solution> {
         let mut solver = super::Solver::new(self);
         solver.solve_all()
     }
 
+    /// Finds all solutions and scores each one by its meanings, returning
+    /// them with their scores, highest score first.  Solutions with the same
+    /// score stay in the order the solver found them.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::sudoku_n;
+    ///
+    /// // Rank 4x4 Sudoku solutions by how big the top row reads as a number.
+    /// let matrix = sudoku_n(2, &[None; 16]);
+    /// let ranked = matrix.solve_all_ranked(|meanings| {
+    ///     let mut top_row: Vec<_> = meanings.iter().filter(|(row, _, _)| *row == 0).collect();
+    ///     top_row.sort();
+    ///     top_row.iter().fold(0, |n, (_, _, value)| n * 10 + i64::from(*value))
+    /// });
+    /// assert_eq!(ranked.len(), 288);
+    /// assert_eq!(ranked[0].0, 4321);
+    /// assert_eq!(ranked.last().unwrap().0, 1234);
+    /// ```
+    #[must_use]
+    pub fn solve_all_ranked(&self, score: impl Fn(&[&T]) -> i64) -> Vec<(i64, Solution)> {
+        let mut ranked: Vec<(i64, Solution)> = self
+            .solve_all()
+            .into_iter()
+            .map(|solution| (score(&solution.meanings(self)), solution))
+            .collect();
+        ranked.sort_by_key(|&(score, _)| core::cmp::Reverse(score));
+        ranked
+    }
+
+    /// Solves the exact cover problem represented by this matrix, returning
+    /// the meanings of the options in each solution.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let matrix = xcc::samples::toy();
+    /// assert_eq!(matrix.solve_all_meanings(), [[&4, &2]]);
+    /// ```
+    #[must_use]
+    pub fn solve_all_meanings(&self) -> Vec<Vec<&T>> {
+        self.solve_all()
+            .into_iter()
+            .map(|solution| solution.meanings(self))
+            .collect()
+    }
+
     /// Solves the matrix, returning a unique solution if there is one, or
     /// `Unique::Ambiguous` if there are multiple solutions. If there are no
     /// solutions, `Unique::None` is returned.
@@ -82,22 +244,413 @@ impl<T> Matrix<T> {
     /// builder.add_primary_item("y");
     /// builder.add_option("a", vec!["x", "y"]);
     /// builder.add_option("b", vec!["x", "y"]);
-    /// let mut matrix = builder.build().expect("could not build matrix");
+    /// let matrix = builder.build().expect("could not build matrix");
     /// assert!(matrix.solve_unique().is_ambiguous());
     /// ```
     ///
-    pub fn solve_unique(&mut self) -> Unique<Solution> {
+    #[must_use]
+    pub fn solve_unique(&self) -> Unique<Solution> {
         let mut solver = super::Solver::new(self);
         solver.solve_unique()
     }
 
+    /// Solves a batch of matrices in parallel, returning the result of
+    /// `solve_unique()` for each one, in the same order as the input.
+    ///
+    /// Each matrix is solved serially, but the matrices are spread across
+    /// rayon's thread pool.  This is useful for validating a large corpus of
+    /// puzzles.  Only available with the `rayon` feature.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::Matrix;
+    ///
+    /// let matrices = vec![xcc::samples::toy(), xcc::samples::toy()];
+    /// let results = Matrix::solve_unique_batch(matrices);
+    /// assert!(results.iter().all(|result| result.is_unique()));
+    /// ```
+    #[cfg(feature = "rayon")]
+    #[must_use]
+    pub fn solve_unique_batch(matrices: Vec<Matrix<T>>) -> Vec<Unique<Solution>>
+    where
+        T: Send,
+    {
+        use rayon::prelude::*;
+        matrices
+            .into_par_iter()
+            .map(|matrix| matrix.solve_unique())
+            .collect()
+    }
+
+    /// Like `solve_unique_batch()`, but gives each matrix its own time limit
+    /// of `per_item`.  A matrix whose search runs out of time gets
+    /// `Err(Timeout)`, without holding up the rest of the batch.  Only
+    /// available with the `rayon` feature.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use xcc::Matrix;
+    ///
+    /// let matrices = vec![xcc::samples::toy(), xcc::samples::toy()];
+    /// let results = Matrix::solve_unique_batch_timed(matrices, Duration::from_secs(60));
+    /// assert!(results.iter().all(|result| result.as_ref().unwrap().is_unique()));
+    /// ```
+    #[cfg(feature = "rayon")]
+    #[must_use]
+    pub fn solve_unique_batch_timed(
+        matrices: Vec<Matrix<T>>,
+        per_item: std::time::Duration,
+    ) -> Vec<Result<Unique<Solution>, super::Timeout>>
+    where
+        T: Send,
+    {
+        use rayon::prelude::*;
+        matrices
+            .into_par_iter()
+            .map(|matrix| super::Solver::new(&matrix).solve_unique_timed(per_item))
+            .collect()
+    }
+
+    /// Searches for solutions in parallel, sending them through a channel as
+    /// they are found.
+    ///
+    /// The search splits at the root: each option for the first item the
+    /// solver would branch on becomes a separate rayon task.  The channel is
+    /// bounded to `bound` solutions, so workers wait while the consumer
+    /// catches up, keeping memory use bounded however many solutions there
+    /// are.  Dropping the receiver stops the search.  Only available with the
+    /// `rayon` feature.
+    ///
+    /// The workers run in the background after this returns, so the matrix
+    /// has to be shared through an `Arc`.  The order of the solutions is not
+    /// deterministic, because it depends on how the workers are scheduled.
+    ///
+    /// The receiver must not be read from a task on the global rayon pool,
+    /// such as a `par_iter` closure or a `rayon::spawn` job.  The workers
+    /// block on a full channel while holding pool threads, so a consumer
+    /// that needs one of those threads to make progress would never run,
+    /// and the search would deadlock.  Read it from an ordinary thread, such
+    /// as the main thread or one started with `std::thread::spawn`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::sync::Arc;
+    ///
+    /// let clues = [None; 16];
+    /// let matrix = Arc::new(xcc::samples::sudoku_n(2, &clues));
+    /// // Read on the main thread, outside the rayon pool.
+    /// let receiver = matrix.solve_parallel_channel(10);
+    /// assert_eq!(receiver.iter().count(), 288);
+    /// ```
+    #[cfg(feature = "rayon")]
+    #[must_use]
+    pub fn solve_parallel_channel(
+        self: &std::sync::Arc<Self>,
+        bound: usize,
+    ) -> std::sync::mpsc::Receiver<Solution>
+    where
+        T: Send + Sync + 'static,
+    {
+        use crate::solver::{SearchEvent, Step};
+        use rayon::prelude::*;
+        let (sender, receiver) = std::sync::mpsc::sync_channel(bound);
+        let matrix = std::sync::Arc::clone(self);
+        rayon::spawn(move || {
+            let mut branches = Vec::new();
+            let mut solver = super::Solver::new(&matrix);
+            if let Some(option_ids) = solver.expand(Vec::new(), &mut branches) {
+                let _ = sender.send(Solution::new(option_ids));
+                return;
+            }
+            branches
+                .into_par_iter()
+                .for_each_with(sender, |sender, branch| {
+                    let mut solver = super::Solver::new(&matrix);
+                    solver.search_stack(&mut vec![branch], |_, event| match event {
+                        SearchEvent::Node { .. } => Step::Continue,
+                        SearchEvent::Solution(option_ids) => {
+                            if sender.send(Solution::new(option_ids.to_vec())).is_ok() {
+                                Step::Continue
+                            } else {
+                                Step::Stop
+                            }
+                        }
+                    });
+                });
+        });
+        receiver
+    }
+
     /// Solves the matrix, returning the first solution found, or `None` if
     /// there are no solutions.
-    pub fn solve_once(&mut self) -> Option<Solution> {
+    #[must_use]
+    pub fn solve_once(&self) -> Option<Solution> {
         let mut solver = super::Solver::new(self);
         solver.solve_once()
     }
 
+    /// Returns the options that appear in every solution, in ascending order.
+    /// For a puzzle, these are the moves that are already forced, which makes
+    /// them good hints.  Returns an empty list if there are no solutions.
+    ///
+    /// This solves the matrix once, and then, for each option in that
+    /// solution, checks whether any solution remains without it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::OptionId;
+    /// use xcc::samples::sudoku_n;
+    ///
+    /// // The toy problem has just one solution, so both its options are forced.
+    /// let matrix = xcc::samples::toy();
+    /// assert_eq!(matrix.forced_options(), [OptionId::new(1), OptionId::new(3)]);
+    ///
+    /// // An empty Sudoku can be filled in many ways.
+    /// let matrix = sudoku_n(2, &[None; 16]);
+    /// assert!(matrix.forced_options().is_empty());
+    /// ```
+    #[must_use]
+    pub fn forced_options(&self) -> Vec<OptionId> {
+        let Some(solution) = self.solve_once() else {
+            return Vec::new();
+        };
+        let mut forced: Vec<OptionId> = solution
+            .iter()
+            .filter(|&option_id| {
+                let mut solver = super::Solver::new(self);
+                solver.with_disabled(&[option_id]);
+                solver.solve_once().is_none()
+            })
+            .collect();
+        forced.sort_unstable();
+        forced
+    }
+
+    /// Solves the matrix, returning the first solution found along with the
+    /// color of every secondary (or colored primary) item that the solution
+    /// colors.  For a graph coloring, that is the color of each vertex.
+    /// Returns `None` if there are no solutions.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::{Color, ItemId};
+    ///
+    /// let matrix = xcc::samples::toy();
+    /// let (solution, colors) = matrix.solve_once_with_colors().unwrap();
+    /// assert_eq!(solution.meanings(&matrix).len(), 2);
+    /// // x is colored A, and y is used without a color.
+    /// assert_eq!(colors.get(&ItemId::new(3)), Some(&Color::new(0)));
+    /// assert_eq!(colors.get(&ItemId::new(4)), None);
+    /// ```
+    #[must_use]
+    pub fn solve_once_with_colors(&self) -> Option<(Solution, BTreeMap<ItemId, Color>)> {
+        let mut solver = super::Solver::new(self);
+        let mut result = None;
+        solver.solve_each_with_colors(super::Limit::Max(1), |solution, colors| {
+            result = Some((solution.clone(), colors.clone()));
+        });
+        result
+    }
+
+    /// Finds up to `n` solutions, along with a flag that is `true` if the
+    /// search stopped because it hit the limit, meaning more solutions may
+    /// exist.  This is useful for showing the first few solutions with a
+    /// "more..." marker.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::toy;
+    ///
+    /// let matrix = toy();
+    /// let (solutions, more) = matrix.solve_capped(5);
+    /// assert_eq!(solutions.len(), 1);
+    /// assert!(!more);
+    /// ```
+    #[must_use]
+    pub fn solve_capped(&self, n: usize) -> (Vec<Solution>, bool) {
+        let mut solver = super::Solver::new(self);
+        solver.solve_capped(n)
+    }
+
+    /// Finds up to `n` solutions with different meanings.  See
+    /// `Solver::solve_distinct_meanings()`.
+    #[must_use]
+    pub fn solve_distinct_meanings(&self, n: usize) -> Vec<Vec<&T>>
+    where
+        T: Eq + core::hash::Hash,
+    {
+        let mut solver = super::Solver::new(self);
+        solver.solve_distinct_meanings(n)
+    }
+
+    /// Returns `true` if the matrix has at least one solution.  This stops at
+    /// the first solution found, without building a `Solution`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let matrix = xcc::samples::toy();
+    /// assert!(matrix.is_satisfiable());
+    /// ```
+    #[must_use]
+    pub fn is_satisfiable(&self) -> bool {
+        let mut solver = super::Solver::new(self);
+        solver.count_solutions(super::Limit::Max(1)) > 0
+    }
+
+    /// Returns `true` if the matrix has more than `k` solutions.  The search
+    /// stops as soon as it finds solution number `k + 1`, and no `Solution`
+    /// values are built along the way.
+    ///
+    /// This is the natural test for an under-constrained puzzle: a puzzle
+    /// with a unique answer is one where `has_more_than(1)` is `false` and
+    /// `is_satisfiable()` is `true`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::sudoku_n;
+    ///
+    /// // An empty 4x4 Sudoku has 288 solutions.
+    /// let matrix = sudoku_n(2, &[None; 16]);
+    /// assert!(matrix.has_more_than(287));
+    /// assert!(!matrix.has_more_than(288));
+    /// ```
+    #[must_use]
+    pub fn has_more_than(&self, k: usize) -> bool {
+        let mut solver = super::Solver::new(self);
+        solver.count_solutions(super::Limit::Max(k.saturating_add(1))) > k
+    }
+
+    /// Counts the solutions by the color they give to a secondary item,
+    /// answering questions like "in how many solutions is this cell red?"
+    /// Solutions that leave the item uncolored aren't counted.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::{Color, ItemId, Matrix};
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["a", "b"]);
+    /// builder.add_secondary_item("x");
+    /// builder.add_option(1, ["a", "x:red"]);
+    /// builder.add_option(2, ["a", "x:blue"]);
+    /// builder.add_option(3, ["b", "x:red"]);
+    /// builder.add_option(4, ["b"]);
+    /// let matrix = builder.build().unwrap();
+    ///
+    /// // Red is the first color the builder saw, then blue.
+    /// let counts = matrix.solution_count_by_color(ItemId::new(2));
+    /// assert_eq!(counts[&Color::new(0)], 2);
+    /// assert_eq!(counts[&Color::new(1)], 1);
+    /// ```
+    #[must_use]
+    pub fn solution_count_by_color(&self, item: ItemId) -> HashMap<Color, usize> {
+        let mut counts = HashMap::new();
+        let mut solver = super::Solver::new(self);
+        solver.solve_each_with_colors(super::Limit::All, |_, colors| {
+            if let Some(&color) = colors.get(&item) {
+                *counts.entry(color).or_insert(0) += 1;
+            }
+        });
+        counts
+    }
+
+    /// Explains why the matrix has no solutions, by finding a set of primary
+    /// and required items that can't all be covered at once.  Returns `None`
+    /// if the matrix has a solution.
+    ///
+    /// This uses deletion: starting from all the primary and required items,
+    /// it tries demoting each one in turn to an optional secondary item, and
+    /// keeps it demoted if the problem is still unsatisfiable.  The result is
+    /// minimal, in that dropping any one item from the core makes the problem
+    /// solvable, but it is not necessarily the smallest such set.
+    ///
+    /// It costs one full solve per primary or required item, plus one to
+    /// check that there are no solutions, so it can take a long time on a
+    /// large matrix.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::{ItemId, Matrix};
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["a", "b", "c", "d"]);
+    /// builder.add_option(1, ["a", "b"]);
+    /// builder.add_option(2, ["a", "c"]);
+    /// builder.add_option(3, ["d"]);
+    /// let matrix = builder.build().unwrap();
+    ///
+    /// // b and c both need a.
+    /// assert_eq!(matrix.unsat_core(), Some(vec![ItemId::new(1), ItemId::new(2)]));
+    /// assert_eq!(xcc::samples::toy().unsat_core(), None);
+    /// ```
+    #[must_use]
+    pub fn unsat_core(&self) -> Option<Vec<ItemId>> {
+        if self.is_satisfiable() {
+            return None;
+        }
+        let mut core: Vec<ItemId> = self
+            .primary_items
+            .union(&self.required_items)
+            .map(ItemId::new)
+            .collect();
+        let mut i = 0;
+        while i < core.len() {
+            let mut candidate = core.clone();
+            candidate.remove(i);
+            if self.covering_only(&candidate).is_satisfiable() {
+                i += 1;
+            } else {
+                core = candidate;
+            }
+        }
+        Some(core)
+    }
+
+    /// Returns a copy of the matrix, without the meanings, in which only the
+    /// given items have to be covered.  Each of them stays primary or
+    /// required, as it was, and every other item becomes an optional
+    /// secondary item.
+    fn covering_only(&self, items: &[ItemId]) -> Matrix<()> {
+        let primary_items: Vec<ItemId> = items
+            .iter()
+            .copied()
+            .filter(|&item| self.is_primary(item))
+            .collect();
+        let mut matrix = Matrix::with_primary_items(self.num_items, &primary_items);
+        for &item in items {
+            if self.is_required(item) {
+                matrix.mark_required(item);
+            }
+        }
+        matrix.soft_items.clone_from(&self.soft_items);
+        matrix.secondary_mode = self.secondary_mode;
+        for option in &self.options {
+            let items: Vec<_> = option
+                .colored_items()
+                .map(|(item, color)| match color {
+                    Some(color) => ColoredItem::with_color(item, color),
+                    None => ColoredItem::new(item),
+                })
+                .collect();
+            let option_id = matrix.add_option((), &items);
+            for &(item, color) in &option.forbidden {
+                matrix.forbid_color(OptionId::new(option_id), item, color);
+            }
+        }
+        matrix
+    }
+
     /// Creates a `Builder` to configure a matrix.
     ///
     /// # Example
@@ -114,6 +667,24 @@ impl<T> Matrix<T> {
         Builder::default()
     }
 
+    /// Builds a matrix from a `Problem`, in the same way as declaring its
+    /// items and adding its options to a `Builder`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as `Builder::build()`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any primary item is colored, just like `Builder::add_option()`.
+    pub fn from_problem(problem: &dyn Problem<T>) -> Result<Matrix<T>, BuildError> {
+        let mut builder = Builder::new();
+        builder.add_primary_items_owned(problem.primary_items());
+        builder.add_secondary_items_owned(problem.secondary_items());
+        builder.add_options(problem.options());
+        builder.build()
+    }
+
     /// Low-level constructor. You almost certainly want to use the
     /// `builder()` method instead of this.
     ///
@@ -121,13 +692,58 @@ impl<T> Matrix<T> {
     #[must_use]
     pub fn new(num_primary_items: usize, num_secondary_items: usize) -> Self {
         let num_items = num_primary_items + num_secondary_items;
+        let mut primary_items = FixedBitSet::with_capacity(num_items);
+        primary_items.set_range(0..num_primary_items, true);
         Matrix {
             num_items,
             num_primary_items,
-            options: vec![],
+            primary_items,
+            required_items: FixedBitSet::with_capacity(num_items),
+            soft_items: FixedBitSet::with_capacity(num_items),
+            secondary_mode: SecondaryMode::default(),
+            forbidding: BTreeMap::new(),
+            options: Vec::new(),
+        }
+    }
+
+    /// Low-level constructor for a matrix whose primary items are not
+    /// necessarily numbered before its secondary items.
+    ///
+    /// Creates a new matrix with `num_items` items, of which the ones listed in
+    /// `primary_items` are primary and the rest are secondary.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::{ItemId, Matrix};
+    /// let matrix = Matrix::<()>::with_primary_items(3, &[ItemId::new(2)]);
+    /// assert!(matrix.is_primary(ItemId::new(2)));
+    /// assert!(!matrix.is_primary(ItemId::new(0)));
+    /// ```
+    #[must_use]
+    pub fn with_primary_items(num_items: usize, primary_items: &[ItemId]) -> Self {
+        let mut primary_bits = FixedBitSet::with_capacity(num_items);
+        for item in primary_items {
+            primary_bits.insert(item.index());
+        }
+        Matrix {
+            num_items,
+            num_primary_items: primary_bits.count_ones(..),
+            primary_items: primary_bits,
+            required_items: FixedBitSet::with_capacity(num_items),
+            soft_items: FixedBitSet::with_capacity(num_items),
+            secondary_mode: SecondaryMode::default(),
+            forbidding: BTreeMap::new(),
+            options: Vec::new(),
         }
     }
 
+    /// Reserves room for at least `additional` more options, so that adding
+    /// a known number of options doesn't reallocate along the way.
+    pub fn reserve_options(&mut self, additional: usize) {
+        self.options.reserve(additional);
+    }
+
     /// Adds an option (row) to the DLX instance, returning the option number.
     pub fn add_option(&mut self, meaning: T, items: &[ColoredItem]) -> usize {
         let mut items_bitset = FixedBitSet::with_capacity(self.num_items);
@@ -135,21 +751,60 @@ impl<T> Matrix<T> {
             items_bitset.insert(ci.item().index());
         }
 
-        let colors: HashMap<ItemId, Color> = items
-            .iter()
-            .filter_map(|ci| ci.color().map(|color| (ci.item(), color)))
-            .collect();
+        let mut colors = vec![None; items_bitset.count_ones(..)];
+        for ci in items {
+            if let Some(color) = ci.color() {
+                colors[items_bitset.count_ones(..ci.item().index())] = Some(color);
+            }
+        }
 
         let option_id = self.options.len();
         self.options.push(OptionData {
             option_id: OptionId::new(self.options.len()),
             items: items_bitset,
             colors,
+            forbidden: Vec::new(),
             meaning,
         });
         option_id
     }
 
+    /// Makes an option rule out a color for a secondary item: a solution
+    /// containing the option can color the item any way but `color`, or
+    /// leave it uncolored.  The option doesn't otherwise use the item.  In
+    /// the builder, this is written `x:!red`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::{Color, ColoredItem, ItemId, Matrix, OptionId};
+    ///
+    /// // Items a and b are primary, x is secondary.
+    /// let (a, b, x) = (ItemId::new(0), ItemId::new(1), ItemId::new(2));
+    /// let red = Color::new(0);
+    /// let mut matrix = Matrix::new(2, 1);
+    /// matrix.add_option(1, &[ColoredItem::new(a), ColoredItem::with_color(x, red)]);
+    /// let not_red = matrix.add_option(2, &[ColoredItem::new(b)]);
+    /// assert_eq!(matrix.solve_all().len(), 1);
+    ///
+    /// matrix.forbid_color(OptionId::new(not_red), x, red);
+    /// assert!(!matrix.is_satisfiable());
+    /// ```
+    pub fn forbid_color(&mut self, option: OptionId, item: ItemId, color: Color) {
+        self.options[option.index()].forbidden.push((item, color));
+        self.forbidding
+            .entry((item, color))
+            .or_default()
+            .push(option);
+    }
+
+    /// Returns the options that forbid `item` from having the color `color`.
+    pub(crate) fn options_forbidding(&self, item: ItemId, color: Color) -> &[OptionId] {
+        self.forbidding
+            .get(&(item, color))
+            .map_or(&[], Vec::as_slice)
+    }
+
     /// Returns the user-defined meaning of the given option.
     ///
     /// # Example
@@ -185,6 +840,405 @@ impl<T> Matrix<T> {
             .filter(move |option| option.items.contains(item.index()))
     }
 
+    /// Removes options that cannot be part of any solution, and returns how
+    /// many were removed.  Option IDs are renumbered afterwards, but the
+    /// remaining options keep their order and meanings.
+    ///
+    /// An option is removed if it *blocks* some primary or required item that
+    /// it doesn't contain itself: every option that contains that item is
+    /// incompatible with it.  Two options are incompatible if they share a
+    /// primary item, or a secondary item that they don't both have with the
+    /// same color (an uncolored secondary item is shared freely in
+    /// `SecondaryMode::Free`).  Removing an option can cause another to block
+    /// an item, so this repeats until nothing changes.
+    ///
+    /// This doesn't change the set of solutions.  A solution that contains a
+    /// blocking option would also need an option covering the blocked item,
+    /// and every such option clashes with the blocking one, so no solution
+    /// contains it.  By induction, no option removed in a later round is in a
+    /// solution either.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::Matrix;
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["a", "b", "c"]);
+    /// builder.add_option(1, ["a", "b"]);
+    /// builder.add_option(2, ["a", "c"]);
+    /// builder.add_option(3, ["c"]);
+    /// let mut matrix = builder.build().unwrap();
+    ///
+    /// // Option 2 leaves nothing to cover b.
+    /// assert_eq!(matrix.prune(), 1);
+    /// assert_eq!(matrix.num_options(), 2);
+    /// assert_eq!(matrix.solve_all()[0].meanings(&matrix), [&1, &3]);
+    /// ```
+    pub fn prune(&mut self) -> usize {
+        let mut alive = FixedBitSet::with_capacity(self.options.len());
+        alive.insert_range(..);
+
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for option in &self.options {
+                if !alive.contains(option.option_id.index()) {
+                    continue;
+                }
+                let blocks_an_item = (0..self.num_items).map(ItemId::new).any(|item| {
+                    (self.is_primary(item) || self.is_required(item))
+                        && !option.items.contains(item.index())
+                        && !self.options_for_item(item).any(|other| {
+                            alive.contains(other.option_id.index())
+                                && self.compatible(option, other)
+                        })
+                });
+                if blocks_an_item {
+                    alive.set(option.option_id.index(), false);
+                    changed = true;
+                }
+            }
+        }
+
+        let num_removed = self.options.len() - alive.count_ones(..);
+        let options = core::mem::take(&mut self.options);
+        for (i, mut option) in options
+            .into_iter()
+            .filter(|option| alive.contains(option.option_id.index()))
+            .enumerate()
+        {
+            option.option_id = OptionId::new(i);
+            self.options.push(option);
+        }
+        self.forbidding.clear();
+        for option in &self.options {
+            for &(item, color) in &option.forbidden {
+                self.forbidding
+                    .entry((item, color))
+                    .or_default()
+                    .push(option.option_id);
+            }
+        }
+        num_removed
+    }
+
+    /// Encodes the problem as a SAT instance in DIMACS CNF format, so that it
+    /// can be handed to an off-the-shelf SAT solver.  Variable `i + 1` is
+    /// true if option `i` is chosen.  Every primary and required item gets a
+    /// clause saying that at least one of its options is chosen, and every
+    /// pair of options that can't appear in the same solution, because they
+    /// share a primary item, disagree about a secondary item, or one forbids
+    /// a color the other uses, gets a clause ruling that out.
+    ///
+    /// The satisfying assignments are exactly the solutions, so counting
+    /// them is a good cross-check on the solver.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let matrix = xcc::samples::toy();
+    /// let cnf = matrix.to_dimacs_cnf();
+    /// assert!(cnf.starts_with("p cnf 5 10\n"));
+    /// // p is in the first three options.
+    /// assert!(cnf.contains("\n1 2 3 0\n"));
+    /// // `p x:B` and `q x:A` disagree about the color of x.
+    /// assert!(cnf.contains("\n-3 -4 0\n"));
+    /// ```
+    #[must_use]
+    pub fn to_dimacs_cnf(&self) -> String {
+        let var = |option: &OptionData<T>| option.option_id.index() + 1;
+        let covering: Vec<Vec<usize>> = (0..self.num_items)
+            .map(ItemId::new)
+            .filter(|&item| self.is_primary(item) || self.is_required(item))
+            .map(|item| self.options_for_item(item).map(var).collect())
+            .collect();
+        let mut conflicts = BTreeSet::new();
+        for item in (0..self.num_items).map(ItemId::new) {
+            let options: Vec<_> = self.options_for_item(item).collect();
+            for (i, a) in options.iter().enumerate() {
+                for b in &options[i + 1..] {
+                    if !self.compatible(a, b) {
+                        conflicts.insert((var(a), var(b)));
+                    }
+                }
+            }
+        }
+        // A forbidden color isn't in the option's items, so the loop above
+        // never pairs the option with the options that use that color.
+        for a in &self.options {
+            for &(item, color) in &a.forbidden {
+                for b in self.options_for_item(item) {
+                    if b.color_of(item) == Some(color) {
+                        let (x, y) = (var(a), var(b));
+                        conflicts.insert((x.min(y), x.max(y)));
+                    }
+                }
+            }
+        }
+
+        let num_clauses = covering.len() + conflicts.len();
+        let mut buf = String::new();
+        let _ = writeln!(buf, "p cnf {} {num_clauses}", self.options.len());
+        for clause in covering {
+            for v in clause {
+                let _ = write!(buf, "{v} ");
+            }
+            buf.push_str("0\n");
+        }
+        for (a, b) in conflicts {
+            let _ = writeln!(buf, "-{a} -{b} 0");
+        }
+        buf
+    }
+
+    /// Returns the transposed incidence matrix, in which each option of this
+    /// matrix becomes a primary item and each item becomes an option whose
+    /// meaning is the original `ItemId`.  Option `i` of this matrix is item
+    /// `i` of the result.
+    ///
+    /// This is for structural analysis rather than solving: exact covers of
+    /// the transpose have no particular meaning for the original problem.
+    /// Colors don't transpose meaningfully, so they are dropped, as is the
+    /// distinction between primary and secondary items.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::{ItemId, OptionId};
+    ///
+    /// let matrix = xcc::samples::toy();
+    /// let transposed = matrix.transpose_incidence();
+    /// assert_eq!(transposed.num_items(), matrix.num_options());
+    /// assert_eq!(transposed.num_options(), matrix.num_items());
+    ///
+    /// // p is in the first three options.
+    /// assert_eq!(transposed.meaning(0), &ItemId::new(0));
+    /// let items: Vec<_> = transposed
+    ///     .items_for_option(OptionId::new(0))
+    ///     .map(|(item, _)| item.get())
+    ///     .collect();
+    /// assert_eq!(items, [0, 1, 2]);
+    /// ```
+    #[must_use]
+    pub fn transpose_incidence(&self) -> Matrix<ItemId> {
+        let mut transposed = Matrix::new(self.options.len(), 0);
+        for item in (0..self.num_items).map(ItemId::new) {
+            let options: Vec<ColoredItem> = self
+                .options_for_item(item)
+                .map(|option| ColoredItem::new(ItemId::new(option.option_id.index())))
+                .collect();
+            transposed.add_option(item, &options);
+        }
+        transposed
+    }
+
+    /// Finds symmetries of the problem that swap two items: each result is
+    /// a transposition of items that maps the set of options onto itself,
+    /// ignoring meanings.  Only items of the same kind are swapped, and an
+    /// item keeps its color in every option.  Symmetries that move more than
+    /// two items at once aren't found directly, although many of them are
+    /// compositions of the ones that are.
+    ///
+    /// Every solution stays a solution when its options are mapped through a
+    /// symmetry, so each one found is a chance to add a symmetry-breaking
+    /// constraint and cut down the search.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::{ItemId, Matrix};
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["a", "b", "c", "d", "e"]);
+    /// builder.add_option(1, ["a", "c"]);
+    /// builder.add_option(2, ["b", "c"]);
+    /// builder.add_option(3, ["a", "d"]);
+    /// builder.add_option(4, ["b", "d"]);
+    /// builder.add_option(5, ["e"]);
+    /// let matrix = builder.build().unwrap();
+    ///
+    /// // Swapping a with b and c with d both keep the options intact.
+    /// let symmetries: Vec<_> = matrix
+    ///     .find_option_automorphisms()
+    ///     .iter()
+    ///     .map(|permutation| permutation.moved_items())
+    ///     .collect();
+    /// assert_eq!(symmetries, [[ItemId::new(0), ItemId::new(1)], [ItemId::new(2), ItemId::new(3)]]);
+    /// ```
+    #[must_use]
+    pub fn find_option_automorphisms(&self) -> Vec<Permutation> {
+        type Signature = (Vec<(ItemId, Option<Color>)>, Vec<(ItemId, Color)>);
+        let signature = |option: &OptionData<T>, swap: &dyn Fn(ItemId) -> ItemId| -> Signature {
+            let mut items: Vec<_> = option
+                .colored_items()
+                .map(|(item, color)| (swap(item), color))
+                .collect();
+            items.sort_unstable();
+            let mut forbidden: Vec<_> = option
+                .forbidden
+                .iter()
+                .map(|&(item, color)| (swap(item), color))
+                .collect();
+            forbidden.sort_unstable();
+            (items, forbidden)
+        };
+        let unswapped: Vec<Signature> = self
+            .options
+            .iter()
+            .map(|option| signature(option, &|item| item))
+            .collect();
+        // Only the options that mention one of the swapped items can change.
+        let mut mentions: Vec<Vec<usize>> = vec![Vec::new(); self.num_items];
+        for (i, option) in self.options.iter().enumerate() {
+            for item in option.items.ones() {
+                mentions[item].push(i);
+            }
+            for &(item, _) in &option.forbidden {
+                mentions[item.index()].push(i);
+            }
+        }
+        let kind = |item: ItemId| {
+            (
+                self.is_primary(item),
+                self.is_required(item),
+                self.is_soft(item),
+            )
+        };
+
+        let mut automorphisms = Vec::new();
+        for a in (0..self.num_items).map(ItemId::new) {
+            for b in (a.index() + 1..self.num_items).map(ItemId::new) {
+                if kind(a) != kind(b) || mentions[a.index()].len() != mentions[b.index()].len() {
+                    continue;
+                }
+                let mut affected: Vec<usize> = mentions[a.index()]
+                    .iter()
+                    .chain(&mentions[b.index()])
+                    .copied()
+                    .collect();
+                affected.sort_unstable();
+                affected.dedup();
+                let swap = |item: ItemId| match item {
+                    item if item == a => b,
+                    item if item == b => a,
+                    item => item,
+                };
+                let mut before: Vec<&Signature> = affected.iter().map(|&i| &unswapped[i]).collect();
+                let mut after: Vec<Signature> = affected
+                    .iter()
+                    .map(|&i| signature(&self.options[i], &swap))
+                    .collect();
+                before.sort_unstable();
+                after.sort_unstable();
+                if before.into_iter().eq(after.iter()) {
+                    automorphisms.push(Permutation::transposition(self.num_items, a, b));
+                }
+            }
+        }
+        automorphisms
+    }
+
+    /// Returns `true` if the two options can both appear in a solution.
+    fn compatible(&self, a: &OptionData<T>, b: &OptionData<T>) -> bool {
+        let forbids = |a: &OptionData<T>, b: &OptionData<T>| {
+            a.forbidden
+                .iter()
+                .any(|&(item, color)| b.color_of(item) == Some(color))
+        };
+        if forbids(a, b) || forbids(b, a) {
+            return false;
+        }
+        a.items.intersection(&b.items).map(ItemId::new).all(|item| {
+            if self.is_primary(item) {
+                return false;
+            }
+            let (x, y) = (a.color_of(item), b.color_of(item));
+            colors_compatible(x, y)
+                || (x.is_none() || y.is_none())
+                    && self.secondary_mode == SecondaryMode::Free
+                    && !self.is_required(item)
+                    && !self.is_soft(item)
+        })
+    }
+
+    /// Returns an iterator over the options that contain every one of the
+    /// given items.  With an empty `items`, this yields every option.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::ItemId;
+    ///
+    /// let matrix = xcc::samples::toy();
+    /// // p and x appear together in `p q x y:A`, `p r x:A y`, and `p x:B`.
+    /// let (p, x) = (ItemId::new(0), ItemId::new(3));
+    /// assert_eq!(matrix.options_for_items(&[p, x]).count(), 3);
+    /// // but q and r never do.
+    /// let (q, r) = (ItemId::new(1), ItemId::new(2));
+    /// assert_eq!(matrix.options_for_items(&[q, r]).count(), 0);
+    /// ```
+    pub fn options_for_items<'a>(
+        &'a self,
+        items: &'a [ItemId],
+    ) -> impl Iterator<Item = &'a OptionData<T>> + 'a {
+        self.options
+            .iter()
+            .filter(move |option| items.iter().all(|item| option.items.contains(item.index())))
+    }
+
+    /// Counts how many options give the item each of its colors.  Options
+    /// that use the item without a color aren't counted.  A color that
+    /// dominates is likely to be committed early in the search.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::{Color, ItemId};
+    ///
+    /// let matrix = xcc::samples::toy();
+    /// // x is colored A in two options and B in one.
+    /// let histogram = matrix.color_histogram(ItemId::new(3));
+    /// assert_eq!(histogram[&Color::new(0)], 2);
+    /// assert_eq!(histogram[&Color::new(1)], 1);
+    /// ```
+    #[must_use]
+    pub fn color_histogram(&self, item: ItemId) -> HashMap<Color, usize> {
+        let mut histogram = HashMap::new();
+        for color in self
+            .options_for_item(item)
+            .filter_map(|option| option.color_of(item))
+        {
+            *histogram.entry(color).or_insert(0) += 1;
+        }
+        histogram
+    }
+
+    /// Returns the distinct colors that the given item has across all
+    /// options, in ascending order.  Options that use the item without a color
+    /// don't contribute anything.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::{Color, ItemId};
+    ///
+    /// let matrix = xcc::samples::toy();
+    /// // x is colored A in two options and B in one.
+    /// let x = ItemId::new(3);
+    /// assert_eq!(matrix.colors_for_item(x), [Color::new(0), Color::new(1)]);
+    /// ```
+    #[must_use]
+    pub fn colors_for_item(&self, item: ItemId) -> Vec<Color> {
+        let mut colors: Vec<Color> = self
+            .options_for_item(item)
+            .filter_map(|option| option.color_of(item))
+            .collect();
+        colors.sort_unstable();
+        colors.dedup();
+        colors
+    }
+
     /// Returns an iterator over the items (columns) for a given option (row).
     ///
     /// # Arguments
@@ -198,45 +1252,169 @@ impl<T> Matrix<T> {
         &self,
         option: OptionId,
     ) -> impl Iterator<Item = (ItemId, Option<Color>)> + '_ {
-        let opt = &self.options[option.index()];
-        opt.items.ones().map(ItemId::new).map(move |item| {
-            for (colored_item, color) in &opt.colors {
-                if *colored_item == item {
-                    return (item, Some(*color));
+        self.options[option.index()].colored_items()
+    }
+}
+
+impl<T: Display> Matrix<T> {
+    /// Renders the matrix as a Graphviz DOT bipartite graph, with a node for
+    /// each item (`i0`, `i1`, ...), a node for each option labeled with its
+    /// meaning, and an edge for each item in each option.  Primary items are
+    /// drawn as solid boxes and secondary items as dashed boxes.  Edges to
+    /// colored items are labeled with the color number.
+    ///
+    /// This is meant for eyeballing small matrices: the output is unreadable
+    /// for anything much bigger than a toy problem.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// let matrix = xcc::samples::toy();
+    /// let dot = matrix.to_dot();
+    /// assert!(dot.starts_with("graph matrix {"));
+    /// assert!(dot.contains("o3 -- i3 [label=\"0\"];"));
+    /// ```
+    #[must_use]
+    pub fn to_dot(&self) -> String {
+        let mut buf = String::from("graph matrix {\n  rankdir=LR;\n");
+        for i in 0..self.num_items {
+            let style = if self.is_primary(ItemId::new(i)) {
+                "solid"
+            } else {
+                "dashed"
+            };
+            let _ = writeln!(buf, "  i{i} [shape=box, style={style}];");
+        }
+        for option in &self.options {
+            // Backslashes first, so the ones escaping quotes stay single.
+            let label = option
+                .meaning
+                .to_string()
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"");
+            let _ = writeln!(buf, "  o{} [label=\"{label}\"];", option.option_id.index());
+        }
+        for option in &self.options {
+            for (item, color) in self.items_for_option(option.option_id) {
+                let (o, i) = (option.option_id.index(), item.index());
+                match color {
+                    Some(color) => {
+                        let _ = writeln!(buf, "  o{o} -- i{i} [label=\"{}\"];", color.index());
+                    }
+                    None => {
+                        let _ = writeln!(buf, "  o{o} -- i{i};");
+                    }
                 }
             }
-            (item, None)
-        })
+        }
+        buf.push_str("}\n");
+        buf
     }
 }
 
-#[derive(Debug)]
+/// A summary of the size of a matrix, returned by `Matrix::stats()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MatrixStats {
+    /// The number of primary items.
+    pub num_primary: usize,
+    /// The number of secondary items.
+    pub num_secondary: usize,
+    /// The number of options.
+    pub num_options: usize,
+    /// The total number of items across all options, i.e., the number of
+    /// nonzero entries in the matrix.
+    pub total_item_uses: usize,
+    /// The number of distinct colors used by the options.
+    pub num_colors: usize,
+}
+
+/// What `OptionData::signature()` returns.
+pub(crate) type Signature<'a> = (&'a FixedBitSet, &'a [Option<Color>], &'a [(ItemId, Color)]);
+
+#[derive(Debug, PartialEq, Eq, Hash)]
 pub struct OptionData<T> {
     // The option number (row number) in the matrix.
     pub option_id: OptionId,
     // The items (primary and secondary) that take part in this option.
     pub items: FixedBitSet,
-    // Map from item ID to color for colored items in this option.
-    pub colors: HashMap<ItemId, Color>,
+    // The color of each item in this option, or `None` if it is uncolored,
+    // in the same order as `items`.  This is much smaller than a map, and
+    // reading it alongside `items` needs no hashing.
+    pub colors: Vec<Option<Color>>,
+    // Colors that this option rules out for secondary items, written
+    // `x:!red` in the builder.  These items aren't in `items`, since the
+    // option doesn't otherwise use them.
+    pub forbidden: Vec<(ItemId, Color)>,
     // The user-defined meaning of this option.
     pub meaning: T,
 }
 
+impl<T> OptionData<T> {
+    /// Returns the items in this option in ascending order, each with its
+    /// color if it has one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::{Color, ItemId, OptionId};
+    ///
+    /// let matrix = xcc::samples::toy();
+    /// // The first option is `p q x y:A`.
+    /// let option = matrix.get_option(OptionId::new(0));
+    /// let items: Vec<_> = option.colored_items().collect();
+    /// assert_eq!(
+    ///     items,
+    ///     [
+    ///         (ItemId::new(0), None),
+    ///         (ItemId::new(1), None),
+    ///         (ItemId::new(3), None),
+    ///         (ItemId::new(4), Some(Color::new(0))),
+    ///     ]
+    /// );
+    /// ```
+    pub fn colored_items(&self) -> impl Iterator<Item = (ItemId, Option<Color>)> + '_ {
+        self.items
+            .ones()
+            .map(ItemId::new)
+            .zip(self.colors.iter().copied())
+    }
+
+    /// Returns the color this option gives to `item`, or `None` if the item
+    /// is uncolored or not in the option at all.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::{Color, ItemId, OptionId};
+    ///
+    /// let matrix = xcc::samples::toy();
+    /// // The first option is `p q x y:A`.
+    /// let option = matrix.get_option(OptionId::new(0));
+    /// assert_eq!(option.color_of(ItemId::new(4)), Some(Color::new(0)));
+    /// assert_eq!(option.color_of(ItemId::new(3)), None);
+    /// ```
+    #[must_use]
+    pub fn color_of(&self, item: ItemId) -> Option<Color> {
+        if item.index() < self.items.len() && self.items.contains(item.index()) {
+            self.colors[self.items.count_ones(..item.index())]
+        } else {
+            None
+        }
+    }
+
+    /// Returns everything about this option except its ID and meaning: its
+    /// items, their colors, and the colors it forbids.  Options with the same
+    /// signature are interchangeable in a solution.
+    pub(crate) fn signature(&self) -> Signature<'_> {
+        (&self.items, &self.colors, &self.forbidden)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use pretty_assertions::assert_eq;
 
-    macro_rules! hashmap {
-        ($($x:expr => $y:expr),*) => {{
-            let mut map = HashMap::new();
-            $(
-                map.insert($x, $y);
-            )*
-            map
-        }};
-    }
-
     fn to_vec(set: &FixedBitSet) -> Vec<usize> {
         set.ones().collect()
     }
@@ -279,37 +1457,28 @@ mod tests {
         builder.add_option("p x:B", ["p", "x:B"]);
         builder.add_option("q x:A", ["q", "x:A"]);
         builder.add_option("r y:B", ["r", "y:B"]);
-        let mut matrix = builder.build().unwrap();
+        let matrix = builder.build().unwrap();
 
         assert_eq!(to_vec(&matrix.options[0].items), [0, 1, 3, 4]);
         assert_eq!(
             matrix.options[0].colors,
-            hashmap![ItemId::new(4) => Color::new(0)]
+            [None, None, None, Some(Color::new(0))]
         );
 
         assert_eq!(to_vec(&matrix.options[1].items), [0, 2, 3, 4]);
         assert_eq!(
             matrix.options[1].colors,
-            hashmap![ItemId::new(3) => Color::new(0)]
+            [None, None, Some(Color::new(0)), None]
         );
 
         assert_eq!(to_vec(&matrix.options[2].items), [0, 3]);
-        assert_eq!(
-            matrix.options[2].colors,
-            hashmap![ItemId::new(3) => Color::new(1)]
-        );
+        assert_eq!(matrix.options[2].colors, [None, Some(Color::new(1))]);
 
         assert_eq!(to_vec(&matrix.options[3].items), [1, 3]);
-        assert_eq!(
-            matrix.options[3].colors,
-            hashmap![ItemId::new(3) => Color::new(0)]
-        );
+        assert_eq!(matrix.options[3].colors, [None, Some(Color::new(0))]);
 
         assert_eq!(to_vec(&matrix.options[4].items), [2, 4]);
-        assert_eq!(
-            matrix.options[4].colors,
-            hashmap![ItemId::new(4) => Color::new(1)]
-        );
+        assert_eq!(matrix.options[4].colors, [None, Some(Color::new(1))]);
 
         let solutions = matrix
             .solve_all()
@@ -319,4 +1488,154 @@ mod tests {
 
         assert_eq!(solutions, [[&"q x:A", &"p r x:A y"]]);
     }
+
+    #[test]
+    fn test_prune_preserves_solutions() {
+        let clues = [
+            None,
+            Some(2),
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(1),
+            Some(4),
+            None,
+            None,
+            None,
+            None,
+            None,
+            Some(3),
+            None,
+        ];
+        let sorted_meanings = |matrix: &Matrix<(usize, usize, u32)>| {
+            let mut solutions: Vec<_> = matrix
+                .solve_all()
+                .iter()
+                .map(|s| {
+                    let mut meanings: Vec<_> = s.meanings(matrix).into_iter().copied().collect();
+                    meanings.sort_unstable();
+                    meanings
+                })
+                .collect();
+            solutions.sort();
+            solutions
+        };
+
+        let mut matrix = crate::samples::sudoku_n(2, &clues);
+        let before = sorted_meanings(&matrix);
+        let num_options = matrix.num_options();
+        let removed = matrix.prune();
+        assert!(removed > 0);
+        assert_eq!(matrix.num_options(), num_options - removed);
+        assert_eq!(sorted_meanings(&matrix), before);
+    }
+
+    /// Counts the satisfying assignments of a DIMACS CNF formula by trying
+    /// every one.
+    fn count_models(cnf: &str) -> usize {
+        let mut lines = cnf.lines();
+        let header: Vec<_> = lines.next().unwrap().split(' ').collect();
+        let num_vars: usize = header[2].parse().unwrap();
+        let clauses: Vec<Vec<isize>> = lines
+            .map(|line| {
+                let mut literals: Vec<isize> =
+                    line.split(' ').map(|lit| lit.parse().unwrap()).collect();
+                assert_eq!(literals.pop(), Some(0));
+                literals
+            })
+            .collect();
+        (0..1usize << num_vars)
+            .filter(|model| {
+                clauses.iter().all(|clause| {
+                    clause.iter().any(|&lit| {
+                        let chosen = model & (1 << (lit.unsigned_abs() - 1)) != 0;
+                        chosen == (lit > 0)
+                    })
+                })
+            })
+            .count()
+    }
+
+    #[test]
+    fn test_dimacs_cnf_models_are_solutions() {
+        let toy = crate::samples::toy();
+        assert_eq!(count_models(&toy.to_dimacs_cnf()), toy.solve_all().len());
+
+        // A path of three vertices.
+        let coloring = crate::samples::graph_coloring(&[(0, 1), (1, 2)], 3);
+        let cnf = coloring.to_dimacs_cnf();
+        assert_eq!(count_models(&cnf), 12);
+        assert_eq!(count_models(&cnf), coloring.solve_all().len());
+    }
+
+    #[test]
+    fn test_unsat_core_includes_required_items() {
+        // b needs option 1 and x needs option 2, but both use a.
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["a", "b"]);
+        builder.add_required_colored_item("x");
+        builder.add_option(1, ["a", "b"]);
+        builder.add_option(2, ["a", "x"]);
+        let matrix = builder.build().unwrap();
+        assert_eq!(
+            matrix.unsat_core(),
+            Some(vec![ItemId::new(1), ItemId::new(2)])
+        );
+
+        // Here the required item alone is the problem.
+        let mut builder = Matrix::builder();
+        builder.add_primary_item("a");
+        builder.add_required_colored_item("x");
+        builder.add_option(1, ["a"]);
+        let matrix = builder.build().unwrap();
+        assert_eq!(matrix.unsat_core(), Some(vec![ItemId::new(1)]));
+    }
+
+    #[test]
+    fn test_options_forbidding_survive_prune() {
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["p", "q"]);
+        builder.add_secondary_item("x");
+        builder.add_option("p red", ["p", "x:red"]);
+        builder.add_option("p blue", ["p", "x:blue"]);
+        builder.add_option("p q", ["p", "q", "x:red"]);
+        builder.add_option("q not red", ["q", "x:!red"]);
+        let mut matrix = builder.build().unwrap();
+        let (x, red) = (ItemId::new(2), Color::new(0));
+        assert_eq!(matrix.options_forbidding(x, red), [OptionId::new(3)]);
+
+        // Option 0 leaves nothing to cover q, so it goes, and the forbidding
+        // option moves down.
+        assert_eq!(matrix.prune(), 1);
+        assert_eq!(matrix.options_forbidding(x, red), [OptionId::new(2)]);
+        assert_eq!(matrix.solve_all().len(), 2);
+    }
+
+    #[test]
+    fn test_dimacs_cnf_respects_forbidden_colors() {
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["p", "q"]);
+        builder.add_secondary_item("x");
+        builder.add_option("p red", ["p", "x:red"]);
+        builder.add_option("p blue", ["p"]);
+        builder.add_option("q not red", ["q", "x:!red"]);
+        let matrix = builder.build().unwrap();
+
+        let cnf = matrix.to_dimacs_cnf();
+        assert!(cnf.contains("-1 -3 0\n"));
+        assert_eq!(count_models(&cnf), 1);
+        assert_eq!(count_models(&cnf), matrix.solve_all().len());
+    }
+
+    #[test]
+    fn test_to_dot_escapes_labels() {
+        let mut matrix = Matrix::new(1, 0);
+        matrix.add_option(
+            r#"a\b "c""#.to_string(),
+            &[ColoredItem::new(ItemId::new(0))],
+        );
+        assert!(matrix.to_dot().contains(r#"o0 [label="a\\b \"c\""];"#));
+    }
 }
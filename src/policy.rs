@@ -0,0 +1,86 @@
+//! A hook for changing what it means for options to agree about a secondary
+//! item.
+
+use crate::types::Color;
+
+/// What happens to another option when the solver chooses an option that
+/// shares a secondary item with it.  Returned by `ColorPolicy::compatible()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Commit {
+    /// The other option can still be chosen.
+    Keep,
+    /// The other option can still be chosen, and the item's color is now
+    /// settled: the chosen option's color is recorded as committed.
+    Commit,
+    /// The other option is hidden.
+    Hide,
+}
+
+/// Decides which options survive when the solver chooses an option that
+/// uses a secondary item.  Install one with `Solver::set_color_policy()`.
+///
+/// When the solver chooses an option that uses a secondary item whose color
+/// isn't settled yet, it calls `compatible()` once for each option that
+/// uses the same item, passing the item's color in the chosen option and in
+/// the other one.  `None` means the option uses the item without a color.
+/// Uncolored uses of a free item, in `SecondaryMode::Free`, are left alone
+/// without consulting the policy.
+///
+/// The policy only affects the search.  Other parts of the crate that
+/// reason about colors, such as `Matrix::to_dimacs_cnf()`, keep the usual
+/// rules, as does `Solver::set_color_aware_counts()`.
+///
+/// # Example
+///
+/// ```
+/// use xcc::{Color, ColorPolicy, Commit, Matrix, Solver};
+///
+/// /// Lets any number of options use an uncolored secondary item, while
+/// /// colors must still agree.
+/// struct SharedUncolored;
+///
+/// impl ColorPolicy for SharedUncolored {
+///     fn compatible(&self, committed: Option<Color>, option_color: Option<Color>) -> Commit {
+///         match (committed, option_color) {
+///             (None, None) => Commit::Keep,
+///             (Some(a), Some(b)) if a == b => Commit::Commit,
+///             _ => Commit::Hide,
+///         }
+///     }
+/// }
+///
+/// let mut builder = Matrix::builder();
+/// builder.add_primary_items(["a", "b"]);
+/// builder.add_secondary_item("x");
+/// builder.add_option(1, ["a", "x"]);
+/// builder.add_option(2, ["b", "x"]);
+/// let matrix = builder.build().unwrap();
+///
+/// let mut solver = Solver::new(&matrix);
+/// assert!(solver.solve_all().is_empty());
+///
+/// let mut solver = Solver::new(&matrix);
+/// solver.set_color_policy(Box::new(SharedUncolored));
+/// assert_eq!(solver.solve_all().len(), 1);
+/// ```
+pub trait ColorPolicy {
+    /// Returns what happens to an option that gives the item the color
+    /// `option_color`, when the solver chooses an option that gives it the
+    /// color `committed`.
+    fn compatible(&self, committed: Option<Color>, option_color: Option<Color>) -> Commit;
+}
+
+/// The usual rules, as described in TAOCP: options that use an item with
+/// the same color can go together, and an uncolored use excludes every other
+/// use.  Installing this policy gives the same solutions as having none.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct DefaultColorPolicy;
+
+impl ColorPolicy for DefaultColorPolicy {
+    fn compatible(&self, committed: Option<Color>, option_color: Option<Color>) -> Commit {
+        match (committed, option_color) {
+            (Some(a), Some(b)) if a == b => Commit::Commit,
+            _ => Commit::Hide,
+        }
+    }
+}
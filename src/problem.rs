@@ -0,0 +1,57 @@
+//! A trait for problems that describe themselves, rather than being put
+//! together with a `Builder`.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+
+/// An exact cover problem, described by its items and options.  Implement
+/// this for problems whose shape is only known at run time, such as ones
+/// loaded from a file or supplied by a plugin, and turn them into a matrix
+/// with `Matrix::from_problem()`.
+///
+/// The trait is object safe, so different kinds of problem can sit behind
+/// `&dyn Problem<T>`.
+///
+/// # Example
+///
+/// ```
+/// use xcc::{Matrix, Problem};
+///
+/// /// Choose one of `n` values for each of two variables, all different.
+/// struct TwoDifferent(usize);
+///
+/// impl Problem<(char, usize)> for TwoDifferent {
+///     fn primary_items(&self) -> Vec<String> {
+///         vec!["a".to_string(), "b".to_string()]
+///     }
+///
+///     fn secondary_items(&self) -> Vec<String> {
+///         (0..self.0).map(|v| format!("v{v}")).collect()
+///     }
+///
+///     fn options(&self) -> Vec<((char, usize), Vec<String>)> {
+///         let mut options = Vec::new();
+///         for var in ['a', 'b'] {
+///             for v in 0..self.0 {
+///                 options.push(((var, v), vec![var.to_string(), format!("v{v}")]));
+///             }
+///         }
+///         options
+///     }
+/// }
+///
+/// let problem: &dyn Problem<(char, usize)> = &TwoDifferent(3);
+/// let matrix = Matrix::from_problem(problem).unwrap();
+/// assert_eq!(matrix.solve_all().len(), 6);
+/// ```
+pub trait Problem<T> {
+    /// Returns the names of the primary items.
+    fn primary_items(&self) -> Vec<String>;
+
+    /// Returns the names of the secondary items.
+    fn secondary_items(&self) -> Vec<String>;
+
+    /// Returns the options, each with its meaning and the names of its
+    /// items, which may be colored as in `Builder::add_option()`.
+    fn options(&self) -> Vec<(T, Vec<String>)>;
+}
@@ -1,6 +1,10 @@
 //! Builders for some common types of XCC problems.
 
-use crate::Matrix;
+use crate::{ItemId, Matrix, OptionId, UniquenessChecker};
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
 
 /// Builds a matrix for the toy problem in equation (49)
 /// of Knuth 7.2.2.1.
@@ -18,7 +22,7 @@ use crate::Matrix;
 /// ```
 /// use xcc::samples::toy;
 ///
-/// let mut matrix = toy();
+/// let matrix = toy();
 /// let solutions = matrix.solve_all();
 /// assert_eq!(solutions.len(), 1);
 /// ```
@@ -35,3 +39,778 @@ pub fn toy() -> Matrix<usize> {
     builder.add_option(5, ["r", "y:B"]);
     builder.build().unwrap()
 }
+
+/// Builds a matrix for a generalized Sudoku puzzle with boxes of size
+/// `box_size` by `box_size`.  The grid is `n` by `n`, where `n = box_size *
+/// box_size`, and each cell holds a value from `1` to `n`.  A `box_size` of 3
+/// gives ordinary Sudoku, and a `box_size` of 4 gives 16x16 "hexadoku".
+///
+/// The `clues` are given in row-major order, with `None` for an empty cell and
+/// `Some(value)` for a given.  The meaning of each option is a tuple `(row,
+/// col, value)`.
+///
+/// Item names use separators (such as `R3,12`), so there is no ambiguity when
+/// values or coordinates have more than one digit.
+///
+/// # Panics
+///
+/// Panics if `clues` does not have exactly `n * n` entries, or if a clue is
+/// outside the range `1..=n`.
+///
+/// # Example
+///
+/// ```
+/// use xcc::samples::sudoku_n;
+///
+/// #[rustfmt::skip]
+/// let clues = [
+///     Some(1), None,    None,    None,
+///     None,    None,    Some(3), None,
+///     None,    Some(4), None,    None,
+///     None,    None,    None,    Some(2),
+/// ];
+/// let matrix = sudoku_n(2, &clues);
+/// let solution = matrix.solve_unique();
+/// assert!(solution.is_unique());
+/// ```
+#[must_use]
+pub fn sudoku_n(box_size: usize, clues: &[Option<u32>]) -> Matrix<(usize, usize, u32)> {
+    let n = box_size * box_size;
+    assert_eq!(clues.len(), n * n, "expected {} clues", n * n);
+    let max_value = u32::try_from(n).expect("grid too large");
+
+    let mut builder = Matrix::builder();
+    // Clashing clues leave some items with no options, which just means
+    // there's no solution.
+    builder.allow_unused_primaries(true);
+    for row in 0..n {
+        for col in 0..n {
+            builder.add_primary_item(format!("F{row},{col}"));
+        }
+    }
+    for prefix in ["R", "C", "B"] {
+        for unit in 0..n {
+            for value in 1..=max_value {
+                builder.add_primary_item(format!("{prefix}{unit},{value}"));
+            }
+        }
+    }
+
+    for (row, cells) in clues.chunks(n).enumerate() {
+        for (col, &clue) in cells.iter().enumerate() {
+            let box_num = row / box_size * box_size + col / box_size;
+            let values = match clue {
+                None => 1..=max_value,
+                Some(value) => {
+                    assert!(
+                        (1..=max_value).contains(&value),
+                        "clue {value} at ({row}, {col}) is out of range"
+                    );
+                    value..=value
+                }
+            };
+            for value in values {
+                builder.add_option(
+                    (row, col, value),
+                    [
+                        format!("F{row},{col}"),
+                        format!("R{row},{value}"),
+                        format!("C{col},{value}"),
+                        format!("B{box_num},{value}"),
+                    ],
+                );
+            }
+        }
+    }
+
+    builder.build().unwrap()
+}
+
+/// Builds a matrix for completing an `n` x `n` Latin square, in which each
+/// row and each column contains each of the values 1 to `n` exactly once.
+/// `clues` lists the `(row, col, value)` cells that are already filled in.
+///
+/// This is the same as `sudoku_n()` without the box constraints: the items
+/// are `F{row},{col}` for each cell, `R{row},{value}` for each row, and
+/// `C{col},{value}` for each column.  The meaning of each option is the
+/// `(row, col, value)` it places.
+///
+/// # Panics
+///
+/// Panics if a clue is outside the square or has a value outside 1 to `n`.
+///
+/// # Example
+///
+/// ```
+/// use xcc::samples::latin_square;
+///
+/// let clues = [(0, 0, 1), (0, 1, 2), (1, 0, 2), (2, 2, 4), (3, 3, 1)];
+/// let matrix = latin_square(4, &clues);
+/// let unique = matrix.solve_unique();
+/// let solution = unique.unique().expect("no unique solution");
+/// let mut cells: Vec<_> = solution.meanings(&matrix).into_iter().copied().collect();
+/// cells.sort();
+/// let square: Vec<u32> = cells.iter().map(|&(_, _, value)| value).collect();
+/// assert_eq!(square, [1, 2, 3, 4, 2, 4, 1, 3, 3, 1, 4, 2, 4, 3, 2, 1]);
+/// ```
+#[must_use]
+pub fn latin_square(n: usize, clues: &[(usize, usize, u32)]) -> Matrix<(usize, usize, u32)> {
+    let max_value = u32::try_from(n).expect("square too large");
+    let mut given = vec![None; n * n];
+    for &(row, col, value) in clues {
+        assert!(
+            row < n && col < n,
+            "clue at ({row}, {col}) is outside the square"
+        );
+        assert!(
+            (1..=max_value).contains(&value),
+            "clue {value} at ({row}, {col}) is out of range"
+        );
+        given[row * n + col] = Some(value);
+    }
+
+    let mut builder = Matrix::builder();
+    // Clashing clues can leave an item with no options: no solution.
+    builder.allow_unused_primaries(true);
+    for row in 0..n {
+        builder.add_primary_items((0..n).map(|col| format!("F{row},{col}")));
+    }
+    for prefix in ["R", "C"] {
+        for unit in 0..n {
+            builder
+                .add_primary_items((1..=max_value).map(|value| format!("{prefix}{unit},{value}")));
+        }
+    }
+
+    for row in 0..n {
+        for col in 0..n {
+            let values = match given[row * n + col] {
+                None => 1..=max_value,
+                Some(value) => value..=value,
+            };
+            for value in values {
+                builder.add_option(
+                    (row, col, value),
+                    [
+                        format!("F{row},{col}"),
+                        format!("R{row},{value}"),
+                        format!("C{col},{value}"),
+                    ],
+                );
+            }
+        }
+    }
+
+    builder.build().unwrap()
+}
+
+/// The direction of a slot in a word-fill grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    /// Left to right.
+    Across,
+    /// Top to bottom.
+    Down,
+}
+
+/// A run of two or more fillable cells in a word-fill grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Slot {
+    /// The row of the first cell.
+    pub row: usize,
+    /// The column of the first cell.
+    pub col: usize,
+    /// Which way the slot runs.
+    pub direction: Direction,
+    /// The number of cells in the slot.
+    pub len: usize,
+}
+
+impl Slot {
+    /// Returns the coordinates of the cells in this slot, in order.
+    pub fn cells(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        (0..self.len).map(move |i| match self.direction {
+            Direction::Across => (self.row, self.col + i),
+            Direction::Down => (self.row + i, self.col),
+        })
+    }
+}
+
+/// The meaning of an option in `word_fill()`: a word placed in a slot.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Word {
+    /// Where the word goes.
+    pub slot: Slot,
+    /// The word itself.
+    pub text: String,
+}
+
+/// Builds a matrix for filling a grid with words from a dictionary, as in a
+/// crossword.
+///
+/// Each string in `grid` is one row.  A `#` marks a blocked cell, and any
+/// other character marks a fillable cell; if that character is a letter, the
+/// word through that cell must have that letter there.  Every horizontal or
+/// vertical run of two or more fillable cells is a slot that must be filled
+/// with a word of the same length from `dictionary`.
+///
+/// Each slot is a primary item, and each cell is a secondary item whose color
+/// is the letter in it, so words that cross must agree on the shared letter.
+/// There is also an uncolored secondary item for each dictionary word, so no
+/// word is used more than once.
+///
+/// # Example
+///
+/// ```
+/// use xcc::samples::word_fill;
+///
+/// let grid = ["..", ".."];
+/// let dictionary = ["ab", "cd", "ac", "bd"];
+/// let matrix = word_fill(&grid, &dictionary);
+///
+/// // "ab" over "cd", or its transpose, "ac" over "bd".
+/// assert_eq!(matrix.solve_all().len(), 2);
+/// ```
+#[allow(clippy::missing_panics_doc)]
+#[must_use]
+pub fn word_fill(grid: &[&str], dictionary: &[&str]) -> Matrix<Word> {
+    let cells: Vec<Vec<char>> = grid.iter().map(|row| row.chars().collect()).collect();
+    let is_open = |row: usize, col: usize| {
+        cells
+            .get(row)
+            .and_then(|r| r.get(col))
+            .is_some_and(|&c| c != '#')
+    };
+
+    let mut slots = Vec::new();
+    for (row, row_cells) in cells.iter().enumerate() {
+        for col in 0..row_cells.len() {
+            if !is_open(row, col) {
+                continue;
+            }
+            for direction in [Direction::Across, Direction::Down] {
+                let (starts_run, next) = match direction {
+                    Direction::Across => (col == 0 || !is_open(row, col - 1), (row, col + 1)),
+                    Direction::Down => (row == 0 || !is_open(row - 1, col), (row + 1, col)),
+                };
+                if !starts_run || !is_open(next.0, next.1) {
+                    continue;
+                }
+                let mut len = 0;
+                let (mut r, mut c) = (row, col);
+                while is_open(r, c) {
+                    len += 1;
+                    match direction {
+                        Direction::Across => c += 1,
+                        Direction::Down => r += 1,
+                    }
+                }
+                let slot = Slot {
+                    row,
+                    col,
+                    direction,
+                    len,
+                };
+                slots.push(slot);
+            }
+        }
+    }
+
+    let slot_name = |slot: &Slot| match slot.direction {
+        Direction::Across => format!("A{},{}", slot.row, slot.col),
+        Direction::Down => format!("D{},{}", slot.row, slot.col),
+    };
+
+    let mut builder = Matrix::builder();
+    // A slot that no word fits leaves its item unused, so there's no fill.
+    builder.allow_unused_primaries(true);
+    builder.add_primary_items(slots.iter().map(slot_name));
+    for (row, row_cells) in cells.iter().enumerate() {
+        for col in 0..row_cells.len() {
+            if is_open(row, col) {
+                builder.add_secondary_item(format!("{row},{col}"));
+            }
+        }
+    }
+    let mut words: Vec<&str> = dictionary.to_vec();
+    words.sort_unstable();
+    words.dedup();
+    builder.add_secondary_items(words.iter().map(|word| format!("={word}")));
+
+    for slot in &slots {
+        for &text in &words {
+            if text.chars().count() != slot.len {
+                continue;
+            }
+            let fits = slot.cells().zip(text.chars()).all(|((r, c), letter)| {
+                let given = cells[r][c];
+                !given.is_alphabetic() || given == letter
+            });
+            if !fits {
+                continue;
+            }
+            let mut items = vec![slot_name(slot), format!("={text}")];
+            items.extend(
+                slot.cells()
+                    .zip(text.chars())
+                    .map(|((r, c), letter)| format!("{r},{c}:{letter}")),
+            );
+            builder.add_option(
+                Word {
+                    slot: *slot,
+                    text: text.to_string(),
+                },
+                items,
+            );
+        }
+    }
+
+    builder.build().unwrap()
+}
+
+/// Builds a matrix for coloring a graph with `num_colors` colors, so that no
+/// two adjacent vertices have the same color.
+///
+/// The vertices are numbered from 0 up to the largest vertex mentioned in
+/// `edges`.  Each vertex is a primary item, and each edge has one uncolored
+/// secondary item per color, so the two ends of an edge can't both take that
+/// color.  The meaning of each option is `(vertex, color)`, with colors
+/// numbered from 0.
+///
+/// Note that every coloring appears once for each permutation of the colors.
+///
+/// # Example
+///
+/// ```
+/// use xcc::samples::graph_coloring;
+///
+/// let triangle = [(0, 1), (1, 2), (2, 0)];
+/// assert_eq!(graph_coloring(&triangle, 3).solve_all().len(), 6);
+/// assert!(!graph_coloring(&triangle, 2).is_satisfiable());
+/// ```
+#[allow(clippy::missing_panics_doc)]
+#[must_use]
+pub fn graph_coloring(edges: &[(usize, usize)], num_colors: usize) -> Matrix<(usize, usize)> {
+    let num_vertices = edges.iter().map(|&(u, v)| u.max(v) + 1).max().unwrap_or(0);
+
+    let mut builder = Matrix::builder();
+    // With no colors, the vertices have no options and there's no coloring.
+    builder.allow_unused_primaries(true);
+    builder.add_primary_items((0..num_vertices).map(|v| format!("v{v}")));
+    for e in 0..edges.len() {
+        builder.add_secondary_items((0..num_colors).map(|c| format!("e{e}c{c}")));
+    }
+
+    for vertex in 0..num_vertices {
+        for color in 0..num_colors {
+            let mut items = vec![format!("v{vertex}")];
+            for (e, &(u, v)) in edges.iter().enumerate() {
+                if u == vertex || v == vertex {
+                    items.push(format!("e{e}c{color}"));
+                }
+            }
+            builder.add_option((vertex, color), items);
+        }
+    }
+
+    builder.build().unwrap()
+}
+
+/// An error from `parse_dimacs_graph()`.
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid DIMACS graph at line {line}: {text}")]
+pub struct DimacsError {
+    /// The line number, starting from 1.
+    pub line: usize,
+    /// The offending line.
+    pub text: String,
+}
+
+/// Parses a graph in DIMACS `.col` format, returning its edges with vertices
+/// numbered from 0, ready to pass to `graph_coloring()`.
+///
+/// Comment lines (`c ...`) and the problem line (`p edge ...`) are skipped;
+/// each edge line looks like `e 1 2`, with vertices numbered from 1.
+///
+/// # Errors
+///
+/// Returns a `DimacsError` if a line is not a comment, problem, or valid edge
+/// line.
+///
+/// # Example
+///
+/// ```
+/// use xcc::samples::parse_dimacs_graph;
+///
+/// let input = "c a triangle\np edge 3 3\ne 1 2\ne 2 3\ne 3 1\n";
+/// let edges = parse_dimacs_graph(input).unwrap();
+/// assert_eq!(edges, [(0, 1), (1, 2), (2, 0)]);
+/// ```
+pub fn parse_dimacs_graph(input: &str) -> Result<Vec<(usize, usize)>, DimacsError> {
+    let mut edges = Vec::new();
+    for (n, line) in input.lines().enumerate() {
+        let error = || DimacsError {
+            line: n + 1,
+            text: line.to_string(),
+        };
+        let mut words = line.split_whitespace();
+        match words.next() {
+            None | Some("c" | "p") => {}
+            Some("e") => {
+                let mut vertex = || {
+                    words
+                        .next()
+                        .and_then(|w| w.parse::<usize>().ok())
+                        .and_then(|v| v.checked_sub(1))
+                        .ok_or_else(error)
+                };
+                let u = vertex()?;
+                let v = vertex()?;
+                edges.push((u, v));
+            }
+            Some(_) => return Err(error()),
+        }
+    }
+    Ok(edges)
+}
+
+/// A symmetry of a square board: takes the board size `n` and a cell `(row,
+/// col)`, and returns the cell that it maps to.
+pub type BoardSymmetry = fn(usize, usize, usize) -> (usize, usize);
+
+/// Returns the 8 symmetries of a square board (the dihedral group of the
+/// square), for reducing board-puzzle solutions to one per symmetry class.
+///
+/// In order, they are: the identity; rotations by 90, 180, and 270 degrees
+/// clockwise; a left-right mirror; a top-bottom mirror; reflection in the main
+/// diagonal (transpose); and reflection in the other diagonal.
+///
+/// # Example
+///
+/// ```
+/// use std::collections::HashSet;
+/// use xcc::samples::board_symmetries;
+///
+/// let n = 3;
+/// let images: HashSet<_> = board_symmetries().iter().map(|f| f(n, 0, 1)).collect();
+/// // The middle of an edge can go to the middle of any of the four edges.
+/// assert_eq!(images, HashSet::from([(0, 1), (1, 0), (1, 2), (2, 1)]));
+///
+/// // A 90 degree clockwise rotation takes the top left corner to the top right.
+/// let rotate = board_symmetries()[1];
+/// assert_eq!(rotate(n, 0, 0), (0, 2));
+/// ```
+#[must_use]
+pub fn board_symmetries() -> [BoardSymmetry; 8] {
+    [
+        |_, r, c| (r, c),
+        |n, r, c| (c, n - 1 - r),
+        |n, r, c| (n - 1 - r, n - 1 - c),
+        |n, r, c| (n - 1 - c, r),
+        |n, r, c| (r, n - 1 - c),
+        |n, r, c| (n - 1 - r, c),
+        |_, r, c| (c, r),
+        |n, r, c| (n - 1 - c, n - 1 - r),
+    ]
+}
+
+/// The meaning of an option in `polyomino_tiling()`: a piece placed on the
+/// board.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Placement {
+    /// The index of the piece in the `pieces` slice.
+    pub piece: usize,
+    /// The `(row, col)` cells that the piece covers, in ascending order.
+    pub cells: Vec<(usize, usize)>,
+}
+
+/// Builds a matrix for tiling a `width` x `height` board with polyominoes,
+/// using each piece exactly once.  Pieces may be rotated and reflected.
+///
+/// Each piece is a list of `(row, col)` cells; it doesn't matter where the
+/// shape sits, since it is moved to the top left corner anyway.  Each piece
+/// and each cell of the board is a primary item.  If the pieces don't add up
+/// to the area of the board, there are no solutions.
+///
+/// # Example
+///
+/// ```
+/// use xcc::samples::polyomino_tiling;
+///
+/// let l_tromino = [(0, 0), (1, 0), (1, 1)];
+/// let matrix = polyomino_tiling(&[&l_tromino, &l_tromino], 3, 2);
+/// assert_eq!(matrix.solve_all().len(), 4);
+/// ```
+#[allow(clippy::missing_panics_doc)]
+#[must_use]
+pub fn polyomino_tiling(
+    pieces: &[&[(usize, usize)]],
+    width: usize,
+    height: usize,
+) -> Matrix<Placement> {
+    let mut builder = Matrix::builder();
+    // A piece that fits nowhere on the board leaves its item unused, which
+    // just means there's no tiling.
+    builder.allow_unused_primaries(true);
+    builder.add_primary_items((0..pieces.len()).map(|p| format!("p{p}")));
+    for row in 0..height {
+        builder.add_primary_items((0..width).map(|col| format!("{row},{col}")));
+    }
+
+    for (piece, &shape) in pieces.iter().enumerate() {
+        for orientation in orientations(shape) {
+            let piece_height = orientation.iter().map(|&(r, _)| r + 1).max().unwrap_or(0);
+            let piece_width = orientation.iter().map(|&(_, c)| c + 1).max().unwrap_or(0);
+            for row in 0..=height.saturating_sub(piece_height) {
+                for col in 0..=width.saturating_sub(piece_width) {
+                    let cells: Vec<_> = orientation
+                        .iter()
+                        .map(|&(r, c)| (row + r, col + c))
+                        .collect();
+                    if cells.iter().any(|&(r, c)| r >= height || c >= width) {
+                        continue;
+                    }
+                    let mut items = vec![format!("p{piece}")];
+                    items.extend(cells.iter().map(|(r, c)| format!("{r},{c}")));
+                    builder.add_option(Placement { piece, cells }, items);
+                }
+            }
+        }
+    }
+
+    builder.build().unwrap()
+}
+
+/// Returns `true` if the board can be tiled with the given pieces.  See
+/// `polyomino_tiling()`.
+///
+/// # Example
+///
+/// ```
+/// use xcc::samples::exists_tiling;
+///
+/// let l_tromino = [(0, 0), (1, 0), (1, 1)];
+/// assert!(exists_tiling(&[&l_tromino, &l_tromino], 3, 2));
+/// assert!(!exists_tiling(&[&l_tromino, &l_tromino], 6, 1));
+/// ```
+#[must_use]
+pub fn exists_tiling(pieces: &[&[(usize, usize)]], width: usize, height: usize) -> bool {
+    polyomino_tiling(pieces, width, height).is_satisfiable()
+}
+
+/// Returns the distinct rotations and reflections of a shape, each moved to
+/// the top left corner and with its cells sorted.
+fn orientations(shape: &[(usize, usize)]) -> Vec<Vec<(usize, usize)>> {
+    let mut result: Vec<Vec<(usize, usize)>> = Vec::new();
+    for transform in 0..8 {
+        let mut cells: Vec<(isize, isize)> = shape
+            .iter()
+            .map(|&(r, c)| {
+                let (r, c) = (r.cast_signed(), c.cast_signed());
+                let (r, c) = if transform & 4 == 0 { (r, c) } else { (c, r) };
+                let r = if transform & 2 == 0 { r } else { -r };
+                let c = if transform & 1 == 0 { c } else { -c };
+                (r, c)
+            })
+            .collect();
+        let min_row = cells.iter().map(|&(r, _)| r).min().unwrap_or(0);
+        let min_col = cells.iter().map(|&(_, c)| c).min().unwrap_or(0);
+        for cell in &mut cells {
+            *cell = (cell.0 - min_row, cell.1 - min_col);
+        }
+        let mut cells: Vec<_> = cells
+            .into_iter()
+            .map(|(r, c)| (r.unsigned_abs(), c.unsigned_abs()))
+            .collect();
+        cells.sort_unstable();
+        if !result.contains(&cells) {
+            result.push(cells);
+        }
+    }
+    result
+}
+
+/// Parses a puzzle grid written as ASCII art, one row per line.
+///
+/// Blank lines are skipped, and whitespace within a line is ignored, so
+/// `"1 . 3"` and `"1.3"` are the same row.  The rows aren't required to be the
+/// same length.
+///
+/// # Example
+///
+/// ```
+/// use xcc::samples::grid_from_ascii;
+///
+/// let grid = grid_from_ascii("
+///     1 . 3
+///     . 2 .
+/// ");
+/// assert_eq!(grid, [['1', '.', '3'], ['.', '2', '.']]);
+/// ```
+#[must_use]
+pub fn grid_from_ascii(s: &str) -> Vec<Vec<char>> {
+    s.lines()
+        .map(|line| {
+            line.chars()
+                .filter(|ch| !ch.is_whitespace())
+                .collect::<Vec<_>>()
+        })
+        .filter(|row| !row.is_empty())
+        .collect()
+}
+
+/// Draws a board one character per cell, the opposite of `grid_from_ascii()`.
+/// `cells(row, col)` gives the character for each cell, with `row` counting
+/// down from 0 to `height - 1` and `col` counting across from 0 to
+/// `width - 1`.  Each row ends with a newline.
+///
+/// # Example
+///
+/// ```
+/// use xcc::samples::render_grid;
+///
+/// let queens = [(0, 1), (1, 3), (2, 0), (3, 2)];
+/// let board = render_grid(4, 4, |row, col| {
+///     if queens.contains(&(row, col)) { 'Q' } else { '.' }
+/// });
+/// assert_eq!(board, ".Q..\n...Q\nQ...\n..Q.\n");
+/// ```
+#[must_use]
+pub fn render_grid(width: usize, height: usize, cells: impl Fn(usize, usize) -> char) -> String {
+    let mut buf = String::with_capacity((width + 1) * height);
+    for row in 0..height {
+        buf.extend((0..width).map(|col| cells(row, col)));
+        buf.push('\n');
+    }
+    buf
+}
+
+/// Removes clues from a puzzle for as long as it still has a unique solution,
+/// returning a minimal set of clues: taking away any one of them would allow
+/// more than one solution.
+///
+/// Each clue is an option of `full` that every solution must use.  `clues`
+/// must already determine a unique solution; a common starting point is all
+/// the options of a solution, as in the example.  The clues are tried for
+/// removal in a random order, so different random sources give different
+/// puzzles.  `rng(n)` should return a random number less than `n`.
+///
+/// # Panics
+///
+/// Panics if `clues` doesn't determine a unique solution.
+///
+/// # Example
+///
+/// ```
+/// use xcc::samples::{minimize_clues, sudoku_n};
+///
+/// // Start from a solved 4x4 Sudoku.
+/// let full = sudoku_n(2, &[None; 16]);
+/// let solved = full.solve_once().unwrap();
+///
+/// let mut seed = 12345_u64;
+/// let rng = |n: usize| {
+///     seed = seed.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+///     (seed >> 33) as usize % n
+/// };
+/// let clues = minimize_clues(&full, solved.option_ids().to_vec(), rng);
+/// assert!(clues.len() < 16);
+///
+/// // The clues make a puzzle with a unique solution.
+/// let mut grid = [None; 16];
+/// for &clue in &clues {
+///     let (row, col, value) = full.get_option(clue).meaning;
+///     grid[row * 4 + col] = Some(value);
+/// }
+/// assert!(sudoku_n(2, &grid).solve_unique().is_unique());
+///
+/// // ...and none of them can be left out.
+/// for &clue in &clues {
+///     let (row, col, _) = full.get_option(clue).meaning;
+///     let mut fewer = grid;
+///     fewer[row * 4 + col] = None;
+///     assert!(sudoku_n(2, &fewer).solve_unique().is_ambiguous());
+/// }
+/// ```
+pub fn minimize_clues<T>(
+    full: &Matrix<T>,
+    clues: Vec<OptionId>,
+    mut rng: impl FnMut(usize) -> usize,
+) -> Vec<OptionId> {
+    let mut checker = UniquenessChecker::new(full);
+    assert!(
+        is_unique_with(&mut checker, full, &clues),
+        "the clues don't determine a unique solution"
+    );
+    let mut order = clues.clone();
+    for i in (1..order.len()).rev() {
+        order.swap(i, rng(i + 1));
+    }
+    let mut kept = clues;
+    for clue in order {
+        let without: Vec<OptionId> = kept.iter().copied().filter(|&c| c != clue).collect();
+        if is_unique_with(&mut checker, full, &without) {
+            kept = without;
+        }
+    }
+    kept
+}
+
+/// Returns `true` if `matrix` has exactly one solution that uses every
+/// option in `clues`.  Each clue is forced by disabling the other options
+/// for its primary items.
+fn is_unique_with<T>(
+    checker: &mut UniquenessChecker<T>,
+    matrix: &Matrix<T>,
+    clues: &[OptionId],
+) -> bool {
+    let mut disabled = Vec::new();
+    for &clue in clues {
+        let clue_items = &matrix.get_option(clue).items;
+        for item in clue_items.ones().map(ItemId::new) {
+            if !matrix.is_primary(item) {
+                continue;
+            }
+            disabled.extend(
+                matrix
+                    .options_for_item(item)
+                    .map(|option| option.option_id)
+                    .filter(|&option_id| option_id != clue),
+            );
+        }
+    }
+    checker.check(&disabled).is_unique()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Solver;
+
+    fn has_solution<T>(matrix: &Matrix<T>) -> bool {
+        Solver::new(matrix).solve_once().is_some()
+    }
+
+    #[test]
+    fn test_sudoku_with_clashing_clues_has_no_solution() {
+        let mut clues = vec![None; 16];
+        clues[0] = Some(1);
+        clues[1] = Some(1);
+        assert!(!has_solution(&sudoku_n(2, &clues)));
+    }
+
+    #[test]
+    fn test_latin_square_with_clashing_clues_has_no_solution() {
+        assert!(!has_solution(&latin_square(2, &[(0, 0, 1), (0, 1, 1)])));
+    }
+
+    #[test]
+    fn test_word_fill_with_no_fitting_word_has_no_solution() {
+        assert!(!has_solution(&word_fill(&[".."], &["abc"])));
+    }
+
+    #[test]
+    fn test_graph_coloring_with_no_colors_has_no_solution() {
+        assert!(!has_solution(&graph_coloring(&[(0, 1)], 0)));
+    }
+}
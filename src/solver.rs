@@ -1,10 +1,18 @@
 use super::Matrix;
+use crate::collections::HashMap;
 use crate::{
-    types::{Color, ItemId, OptionId},
+    policy::{ColorPolicy, Commit},
+    types::{Color, ItemId, OptionId, SecondaryMode},
     Unique,
 };
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::hash::{BuildHasher, Hash, Hasher};
+use core::{iter, slice};
 use fixedbitset::FixedBitSet;
-use std::collections::HashMap;
 
 /// A solver for an exact cover problem with colored secondary items.
 pub struct Solver<'a, T> {
@@ -13,8 +21,27 @@ pub struct Solver<'a, T> {
     available_items: FixedBitSet,
     /// Bitmask of options that can still be used.
     available_options: FixedBitSet,
-    /// Map of item => color that we have committed to
-    committed_colors: HashMap<ItemId, Color>,
+    /// Map of item => color that we have committed to.  This is ordered, so
+    /// that anything that walks it sees the same order on every run.
+    committed_colors: BTreeMap<ItemId, Color>,
+    /// Whether to commit forced options before branching.
+    propagation: bool,
+    /// Whether branching counts skip options that conflict with
+    /// `committed_colors`.
+    color_aware_counts: bool,
+    /// Whether the solver may also branch on secondary items.
+    secondary_branching: bool,
+    /// Per-item weights for choosing an item to branch on, indexed by item.
+    /// `None` means every item has weight 1.
+    item_weights: Option<Vec<f64>>,
+    /// For some secondary items, the colors to try first, best first.
+    color_preferences: BTreeMap<ItemId, Vec<Color>>,
+    /// How options that share a secondary item get along, if not in the
+    /// usual way.
+    color_policy: Option<Box<dyn ColorPolicy>>,
+    /// Where to write a trace of the search, if anywhere.
+    #[cfg(feature = "std")]
+    logger: Option<Box<dyn std::io::Write + 'a>>,
 }
 
 impl<'a, T> Solver<'a, T> {
@@ -37,103 +64,1619 @@ impl<'a, T> Solver<'a, T> {
             matrix,
             available_items,
             available_options,
-            committed_colors: HashMap::new(),
+            committed_colors: BTreeMap::new(),
+            propagation: false,
+            color_aware_counts: false,
+            secondary_branching: false,
+            item_weights: None,
+            color_preferences: BTreeMap::new(),
+            color_policy: None,
+            #[cfg(feature = "std")]
+            logger: None,
         }
     }
 
+    /// Turns unit propagation on or off.  It is off by default.
+    ///
+    /// With propagation on, whenever an uncovered primary item has exactly one
+    /// option left, the solver commits to that option straight away, and
+    /// repeats until no such item remains, before choosing an item to branch
+    /// on.  This finds the same solutions, but can make the search tree much
+    /// smaller for problems like Sudoku, where each placement forces others.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::toy;
+    /// use xcc::{Limit, Solver};
+    ///
+    /// let matrix = toy();
+    /// let mut solver = Solver::new(&matrix);
+    /// solver.set_propagation(true);
+    /// assert_eq!(solver.solve(Limit::All).len(), 1);
+    /// ```
+    pub fn set_propagation(&mut self, propagation: bool) {
+        self.propagation = propagation;
+    }
+
+    /// Chooses whether the branching heuristic ignores options whose colors
+    /// conflict with colors the solver has already committed to.  It is off
+    /// by default, which counts every visible option.
+    ///
+    /// The solver picks the item with the fewest remaining options to branch
+    /// on.  With this turned on, an option only counts if each of its colored
+    /// items is either uncommitted or committed to the same color.  Since
+    /// `commit` hides most conflicting options straight away, this usually
+    /// makes no difference, but it keeps the counts exact whenever a
+    /// conflicting option is still visible.
+    pub fn set_color_aware_counts(&mut self, color_aware_counts: bool) {
+        self.color_aware_counts = color_aware_counts;
+    }
+
+    /// Chooses whether the solver may branch on secondary items as well as
+    /// primary ones.  It is off by default.
+    ///
+    /// With this turned on, the solver picks the item with the fewest
+    /// branches among all the items still in play, as in the textbook version
+    /// of Algorithm C.  A secondary item with `k` visible options has `k + 1`
+    /// branches: one for each option that could be the first to use it, and
+    /// one in which nothing uses it.  This finds the same solutions, and can
+    /// shrink the search when the secondary items are the tightest
+    /// constraints.  Free secondary items (see `SecondaryMode::Free`) are
+    /// never branched on.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::toy;
+    /// use xcc::{Limit, Solver};
+    ///
+    /// let matrix = toy();
+    /// let mut solver = Solver::new(&matrix);
+    /// solver.set_secondary_branching(true);
+    /// assert_eq!(solver.solve(Limit::All).len(), 1);
+    /// ```
+    pub fn set_secondary_branching(&mut self, secondary_branching: bool) {
+        self.secondary_branching = secondary_branching;
+    }
+
+    /// Weights the items, to steer the choice of which item to branch on.
+    /// Items missing from `weights` have weight 1.0.
+    ///
+    /// Normally the solver branches on the item with the fewest remaining
+    /// options.  With weights, it branches on the item with the smallest
+    /// `count / weight`, where `count` is the number of branches for the item
+    /// (its remaining options).  So an item with weight 2.0 and 6 options is
+    /// tried before one with weight 1.0 and 4 options.  Ties go to the item
+    /// with the lowest ID.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a weight is not finite and greater than zero, or if an item
+    /// ID is not in the matrix.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::collections::HashMap;
+    /// use xcc::samples::sudoku_n;
+    /// use xcc::{ItemId, Limit, Solver};
+    ///
+    /// // Prefer the cell items (the first 16) to the row, column, and box
+    /// // items.
+    /// let matrix = sudoku_n(2, &[None; 16]);
+    /// let weights = (0..16).map(|i| (ItemId::new(i), 2.0)).collect::<HashMap<_, _>>();
+    /// let mut solver = Solver::new(&matrix);
+    /// solver.set_item_weights(weights);
+    /// assert_eq!(solver.count_solutions(Limit::All), 288);
+    /// ```
+    pub fn set_item_weights(&mut self, weights: HashMap<ItemId, f64>) {
+        let mut item_weights = vec![1.0; self.matrix.num_items()];
+        for (item, weight) in weights {
+            assert!(
+                weight.is_finite() && weight > 0.0,
+                "item {item:?} has weight {weight}, but weights must be finite and positive"
+            );
+            assert!(
+                item.index() < item_weights.len(),
+                "item {item:?} is not in the matrix"
+            );
+            item_weights[item.index()] = weight;
+        }
+        self.item_weights = Some(item_weights);
+    }
+
+    /// Asks the solver to try options that give `item` the colors in `order`
+    /// before other options, trying the first color in `order` first.  This
+    /// doesn't change which solutions there are, only the order they are
+    /// found in, so it steers `solve_once()` towards a preferred coloring.
+    ///
+    /// When an option colors several items that have preferences, its most
+    /// preferred color counts.  Calling this again for the same item replaces
+    /// its preference.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::{ItemId, Matrix, Solver};
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_item("a");
+    /// builder.add_secondary_item("x");
+    /// builder.add_option("red", ["a", "x:red"]);
+    /// builder.add_option("blue", ["a", "x:blue"]);
+    /// let (matrix, colors) = builder.build_with_color_interner().unwrap();
+    ///
+    /// let x = ItemId::new(1);
+    /// let mut solver = Solver::new(&matrix);
+    /// solver.set_color_preference(x, vec![colors.get("red").unwrap()]);
+    /// assert_eq!(solver.solve_once().unwrap().meanings(&matrix), [&"red"]);
+    ///
+    /// let mut solver = Solver::new(&matrix);
+    /// solver.set_color_preference(x, vec![colors.get("blue").unwrap()]);
+    /// assert_eq!(solver.solve_once().unwrap().meanings(&matrix), [&"blue"]);
+    /// ```
+    pub fn set_color_preference(&mut self, item: ItemId, order: Vec<Color>) {
+        self.color_preferences.insert(item, order);
+    }
+
+    /// Returns how strongly the color preferences favor an option: 0 for the
+    /// most preferred color, and `usize::MAX` if the option doesn't give any
+    /// item a preferred color.
+    fn preference_rank(&self, option_id: OptionId) -> usize {
+        self.matrix
+            .items_for_option(option_id)
+            .filter_map(|(item, color)| {
+                let order = self.color_preferences.get(&item)?;
+                order.iter().position(|&c| Some(c) == color)
+            })
+            .min()
+            .unwrap_or(usize::MAX)
+    }
+
+    /// Replaces the rules for options that share a secondary item with a
+    /// custom `ColorPolicy`.  Without one, the solver follows the rules of
+    /// `DefaultColorPolicy`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::toy;
+    /// use xcc::{DefaultColorPolicy, Solver};
+    ///
+    /// let matrix = toy();
+    /// let mut solver = Solver::new(&matrix);
+    /// solver.set_color_policy(Box::new(DefaultColorPolicy));
+    /// assert_eq!(solver.solve_all(), matrix.solve_all());
+    /// ```
+    pub fn set_color_policy(&mut self, policy: Box<dyn ColorPolicy>) {
+        self.color_policy = Some(policy);
+    }
+
+    /// Disables the given options, so that the solver behaves as if the
+    /// matrix didn't have them.  The matrix itself is untouched, so this is a
+    /// cheap way to ask "what if this placement weren't allowed?"
+    ///
+    /// The options stay disabled until `reset()` is called.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::sudoku_n;
+    /// use xcc::{Limit, OptionId, Solver};
+    ///
+    /// // Option 0 puts a 1 in the top left corner of a 4x4 Sudoku.
+    /// let matrix = sudoku_n(2, &[None; 16]);
+    /// let mut solver = Solver::new(&matrix);
+    /// solver.with_disabled(&[OptionId::new(0)]);
+    /// assert_eq!(solver.count_solutions(Limit::All), 288 - 72);
+    ///
+    /// solver.reset();
+    /// assert_eq!(solver.count_solutions(Limit::All), 288);
+    /// ```
+    pub fn with_disabled(&mut self, ids: &[OptionId]) {
+        for id in ids {
+            self.available_options.set(id.index(), false);
+        }
+    }
+
+    /// Fixes the color of a secondary item before solving, as if an option
+    /// that gives it that color had already been chosen: options that use
+    /// the item with any other color, or without a color, are hidden.  No
+    /// option needs to exist that forces the color.  Like disabled options,
+    /// committed colors last until `reset()`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `Conflict` if the item has already been given a different
+    /// color.  Committing the same color again is fine.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::toy;
+    /// use xcc::{Color, ItemId, Solver};
+    ///
+    /// let matrix = toy();
+    /// let (x, a, b) = (ItemId::new(3), Color::new(0), Color::new(1));
+    /// let mut solver = Solver::new(&matrix);
+    /// solver.commit_color(x, b).unwrap();
+    /// assert!(solver.solve_all().is_empty());
+    ///
+    /// solver.reset();
+    /// solver.commit_color(x, a).unwrap();
+    /// assert_eq!(solver.solve_all().len(), 1);
+    /// assert!(solver.commit_color(x, b).is_err());
+    /// ```
+    pub fn commit_color(&mut self, item: ItemId, color: Color) -> Result<(), Conflict> {
+        match self.committed_colors.get(&item) {
+            Some(&committed) if committed == color => Ok(()),
+            Some(&committed) => Err(Conflict {
+                item,
+                committed,
+                requested: color,
+            }),
+            None => {
+                self.purify(item, color);
+                self.committed_colors.insert(item, color);
+                // As in `commit`, the item is settled, so secondary branching
+                // mustn't pick it.  Required and soft items are still waiting
+                // for an option to cover them, though.
+                if !self.matrix.is_required(item) && !self.matrix.is_soft(item) {
+                    self.available_items.set(item.index(), false);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Puts the solver back in its starting state, with every item and
+    /// option available and no colors committed.  This undoes
+    /// `with_disabled()`, and whatever state a previous search left behind.
+    /// Settings such as propagation and item weights are kept.
+    pub fn reset(&mut self) {
+        self.available_items.insert_range(..);
+        self.available_options.insert_range(..);
+        self.committed_colors.clear();
+    }
+
+    /// Writes a trace of the search to `w`, one line per step: branching on
+    /// an item, covering an item, committing to an option, purifying an item
+    /// to a color, and backtracking after a solution, a dead end, or a branch
+    /// that was pruned.  Items, options, and colors are shown by number.
+    /// Every way of solving gives the same trace for the same search.  This
+    /// is for debugging, and comparing a run against a known-good one;
+    /// without a logger, tracing costs nothing.  Only available with the
+    /// `std` feature.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::toy;
+    /// use xcc::Solver;
+    ///
+    /// let matrix = toy();
+    /// let mut trace = Vec::new();
+    /// let mut solver = Solver::new(&matrix);
+    /// solver.set_logger(&mut trace);
+    /// solver.solve_all();
+    /// drop(solver);
+    ///
+    /// let trace = String::from_utf8(trace).unwrap();
+    /// assert!(trace.starts_with("branch on item 1\ncover item 1 (2 options hidden)\n"));
+    /// assert!(trace.contains("purify item 3 to color 0\n"));
+    /// assert!(trace.contains("backtrack\n"));
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn set_logger(&mut self, w: impl std::io::Write + 'a) {
+        self.logger = Some(Box::new(w));
+    }
+
+    /// Writes a line to the logger, if there is one.
+    #[cfg(feature = "std")]
+    fn log(&mut self, args: core::fmt::Arguments) {
+        if let Some(logger) = &mut self.logger {
+            let _ = writeln!(logger, "{args}");
+        }
+    }
+
+    #[cfg(not(feature = "std"))]
+    #[allow(clippy::unused_self)]
+    fn log(&mut self, _args: core::fmt::Arguments) {}
+
     /// Solves the exact cover problem represented by this matrix, returning all
     /// solutions.
     pub fn solve_all(&mut self) -> Vec<Solution> {
         self.solve(Limit::All)
     }
 
-    /// Solves the exact cover problem represented by this matrix, searching for
-    /// up to two solutions.  If no solutions are found, returns `None`.  If one
-    /// solution is found, returns `One(solution)`.  If two solutions are found,
-    /// returns `Ambiguous(s1, s2)`.
-    pub fn solve_unique(&mut self) -> Unique<Solution> {
-        let mut solutions = self.solve(Limit::Max(2));
-        let s1 = solutions.pop();
-        let s2 = solutions.pop();
+    /// Solves the exact cover problem represented by this matrix, searching for
+    /// up to two solutions.  If no solutions are found, returns `None`.  If one
+    /// solution is found, returns `One(solution)`.  If two solutions are found,
+    /// returns `Ambiguous(s1, s2)`.
+    pub fn solve_unique(&mut self) -> Unique<Solution> {
+        to_unique(self.solve(Limit::Max(2)))
+    }
+
+    /// Like `solve_unique()`, but gives up with `Err(Timeout)` if the search
+    /// takes longer than `time_limit`.  The clock is checked every few
+    /// hundred nodes of the search tree, so it stops promptly however the
+    /// tree is shaped.  Only available with the `std` feature.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Timeout` if the time limit runs out before the search
+    /// finishes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::time::Duration;
+    /// use xcc::samples::sudoku_n;
+    /// use xcc::{Solver, Timeout};
+    ///
+    /// let matrix = sudoku_n(2, &[None; 16]);
+    /// let mut solver = Solver::new(&matrix);
+    /// let result = solver.solve_unique_timed(Duration::from_secs(60));
+    /// assert!(result.unwrap().is_ambiguous());
+    ///
+    /// let mut solver = Solver::new(&matrix);
+    /// assert_eq!(solver.solve_unique_timed(Duration::ZERO).unwrap_err(), Timeout);
+    /// ```
+    #[cfg(feature = "std")]
+    pub fn solve_unique_timed(
+        &mut self,
+        time_limit: std::time::Duration,
+    ) -> Result<Unique<Solution>, Timeout> {
+        // Reading the clock costs far more than expanding a typical node.
+        const NODES_PER_CLOCK_CHECK: usize = 256;
+
+        let start = std::time::Instant::now();
+        let mut solutions = Vec::new();
+        let mut num_nodes = 0;
+        let mut timed_out = false;
+        self.search_with(|_, event| match event {
+            SearchEvent::Node { .. } => {
+                num_nodes += 1;
+                if num_nodes % NODES_PER_CLOCK_CHECK == 1 && start.elapsed() >= time_limit {
+                    timed_out = true;
+                    return Step::Stop;
+                }
+                Step::Continue
+            }
+            SearchEvent::Solution(option_ids) => {
+                solutions.push(Solution::new(option_ids.to_vec()));
+                if solutions.len() == 2 {
+                    Step::Stop
+                } else {
+                    Step::Continue
+                }
+            }
+        });
+        if timed_out {
+            return Err(Timeout);
+        }
+        Ok(to_unique(solutions))
+    }
+
+    /// Solves the exact cover problem represented by this matrix, returning the
+    /// first solution found.
+    pub fn solve_once(&mut self) -> Option<Solution> {
+        self.solve(Limit::Max(1)).pop()
+    }
+
+    /// Stack-based solver for the exact cover problem.
+    ///
+    /// # Arguments
+    ///
+    /// * `max_solutions` - The maximum number of solutions to return.  If `None`,
+    ///   all solutions will be returned.
+    ///
+    /// # Returns
+    ///
+    /// A vector of solutions.  If `max_solutions` is `None`, this will be all
+    /// solutions.  If `max_solutions` is `Some(n)`, this will be at most `n`
+    /// solutions.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::toy;
+    /// use xcc::{Limit, Solver};
+    ///
+    /// let matrix = toy();
+    /// let mut solver = Solver::new(&matrix);
+    /// let solutions = solver.solve(Limit::All);
+    /// assert_eq!(solutions.len(), 1);
+    /// ```
+    pub fn solve(&mut self, limit: Limit) -> Vec<Solution> {
+        let mut results = Vec::new();
+        self.search(|_, option_ids| {
+            results.push(Solution::new(option_ids.to_vec()));
+            !limit.reached(results.len())
+        });
+        results
+    }
+
+    /// Explains whether an option could be chosen in the solver's current
+    /// state, and if not, why not.  An option is ruled out if it uses an
+    /// item that has already been covered, or gives a secondary item a
+    /// different color from the one committed to it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::collections::BTreeMap;
+    /// use xcc::samples::toy;
+    /// use xcc::{Color, FixedBitSet, ItemId, OptionId, OptionStatus, SavedState, Solver};
+    ///
+    /// let matrix = toy();
+    /// let solver = Solver::new(&matrix);
+    /// assert_eq!(solver.explain_option(OptionId::new(2)), OptionStatus::Available);
+    ///
+    /// // Resume from a state where x (item 3) has been colored A.
+    /// let (x, a, b) = (ItemId::new(3), Color::new(0), Color::new(1));
+    /// let mut items = FixedBitSet::with_capacity(5);
+    /// items.insert_range(..);
+    /// items.set(3, false);
+    /// let mut options = FixedBitSet::with_capacity(5);
+    /// options.insert_range(..);
+    /// options.set(0, false);
+    /// options.set(2, false);
+    /// let state = SavedState::new(items, options, BTreeMap::from([(x, a)]));
+    /// let solver = Solver::resume(&matrix, state);
+    ///
+    /// // "p x:B" wants x to be B.
+    /// assert_eq!(
+    ///     solver.explain_option(OptionId::new(2)),
+    ///     OptionStatus::ColorConflict(x, Some(b), a)
+    /// );
+    /// // "p q x y:A" uses x without a color.
+    /// assert_eq!(
+    ///     solver.explain_option(OptionId::new(0)),
+    ///     OptionStatus::ColorConflict(x, None, a)
+    /// );
+    /// ```
+    #[must_use]
+    pub fn explain_option(&self, option_id: OptionId) -> OptionStatus {
+        for (item, color) in self.matrix.items_for_option(option_id) {
+            if self.available_items.contains(item.index()) {
+                continue;
+            }
+            match self.committed_colors.get(&item) {
+                Some(&committed) if !self.matrix.is_primary(item) => match color {
+                    Some(color) if color == committed => {}
+                    None if self.is_free(item) => {}
+                    _ => return OptionStatus::ColorConflict(item, color, committed),
+                },
+                _ => return OptionStatus::Covered(item),
+            }
+        }
+        if self.available_options.contains(option_id.index()) {
+            OptionStatus::Available
+        } else {
+            OptionStatus::Excluded
+        }
+    }
+
+    /// Returns the colors that `item` could still take, in ascending order:
+    /// the colors it has in options that are still available in the current
+    /// state.  If the item's color has already been committed, that's the
+    /// only color returned.  These are the "pencil marks" for a colored
+    /// puzzle.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use std::collections::BTreeMap;
+    /// use xcc::samples::toy;
+    /// use xcc::{Color, FixedBitSet, ItemId, SavedState, Solver};
+    ///
+    /// let matrix = toy();
+    /// let (x, y, a, b) = (ItemId::new(3), ItemId::new(4), Color::new(0), Color::new(1));
+    /// let solver = Solver::new(&matrix);
+    /// assert_eq!(solver.domain(y), [a, b]);
+    ///
+    /// // Resume from a state where x has been colored A, hiding the options
+    /// // "p q x y:A" and "p x:B".
+    /// let mut items = FixedBitSet::with_capacity(5);
+    /// items.insert_range(..);
+    /// items.set(3, false);
+    /// let mut options = FixedBitSet::with_capacity(5);
+    /// options.insert_range(..);
+    /// options.set(0, false);
+    /// options.set(2, false);
+    /// let state = SavedState::new(items, options, BTreeMap::from([(x, a)]));
+    /// let solver = Solver::resume(&matrix, state);
+    /// assert_eq!(solver.domain(x), [a]);
+    /// assert_eq!(solver.domain(y), [b]);
+    /// ```
+    #[must_use]
+    pub fn domain(&self, item: ItemId) -> Vec<Color> {
+        if let Some(&color) = self.committed_colors.get(&item) {
+            return vec![color];
+        }
+        let mut colors: Vec<Color> = self
+            .matrix
+            .options_for_item(item)
+            .filter(|option| self.explain_option(option.option_id) == OptionStatus::Available)
+            .filter_map(|option| option.color_of(item))
+            .collect();
+        colors.sort_unstable();
+        colors.dedup();
+        colors
+    }
+
+    /// Like `solve()`, but returns an error if the matrix is degenerate,
+    /// so that a matrix with nothing in it isn't mistaken for an
+    /// unsatisfiable one.  `Ok(vec![])` always means there are no solutions.
+    ///
+    /// # Errors
+    ///
+    /// Returns `SolveError::NoPrimaryItems` if the matrix has no primary or
+    /// required items, and `SolveError::NoOptions` if it has no options.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::{Limit, Matrix, SolveError, Solver};
+    ///
+    /// let matrix: Matrix<()> = Matrix::new(2, 0);
+    /// let mut solver = Solver::new(&matrix);
+    /// assert_eq!(solver.try_solve(Limit::All).unwrap_err(), SolveError::NoOptions);
+    ///
+    /// let matrix = xcc::samples::toy();
+    /// let mut solver = Solver::new(&matrix);
+    /// assert_eq!(solver.try_solve(Limit::All).unwrap().len(), 1);
+    /// ```
+    pub fn try_solve(&mut self, limit: Limit) -> Result<Vec<Solution>, SolveError> {
+        let num_items = self.matrix.num_items();
+        let must_cover = (0..num_items)
+            .map(ItemId::new)
+            .any(|item| self.matrix.is_primary(item) || self.matrix.is_required(item));
+        if !must_cover {
+            return Err(SolveError::NoPrimaryItems);
+        }
+        if self.matrix.num_options() == 0 {
+            return Err(SolveError::NoOptions);
+        }
+        Ok(self.solve(limit))
+    }
+
+    /// Returns a snapshot of the solver's current state.
+    #[must_use]
+    pub fn snapshot(&self) -> SavedState {
+        self.save_state()
+    }
+
+    /// Creates a solver for `matrix` that starts from a saved state, such as
+    /// one returned by `snapshot()`.  Solutions found from there contain only
+    /// the options chosen after the snapshot was taken.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the state's bitsets don't match the size of the matrix.
+    #[must_use]
+    pub fn resume(matrix: &'a Matrix<T>, state: SavedState) -> Self {
+        assert_eq!(
+            state.available_items.len(),
+            matrix.num_items(),
+            "saved state has the wrong number of items"
+        );
+        assert_eq!(
+            state.available_options.len(),
+            matrix.num_options(),
+            "saved state has the wrong number of options"
+        );
+        let mut solver = Solver::new(matrix);
+        solver.restore(state);
+        solver
+    }
+
+    /// Finds solutions like `solve()`, calling `progress` with the fraction
+    /// of the search that has been completed so far, between 0 and 1.
+    ///
+    /// The fraction counts how many of the branches at the top of the search
+    /// tree have been finished, so it only moves when one of them is done,
+    /// and parts of the tree can take much longer than others.  It never goes
+    /// backwards, though, and reaches 1 when the search runs to the end.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::sudoku_n;
+    /// use xcc::{Limit, Solver};
+    ///
+    /// let matrix = sudoku_n(2, &[None; 16]);
+    /// let mut solver = Solver::new(&matrix);
+    /// let mut fractions = Vec::new();
+    /// let solutions = solver.solve_with_progress(Limit::All, |f| fractions.push(f));
+    /// assert_eq!(solutions.len(), 288);
+    /// assert!(fractions.windows(2).all(|w| w[0] <= w[1]));
+    /// assert_eq!(fractions.last(), Some(&1.0));
+    /// ```
+    pub fn solve_with_progress(
+        &mut self,
+        limit: Limit,
+        mut progress: impl FnMut(f64),
+    ) -> Vec<Solution> {
+        let mut results = Vec::new();
+        // The number of top-level branches, which is known once the root has
+        // been expanded, and how many of them haven't been started.  Those
+        // are always at the bottom of the stack.
+        let mut at_root = true;
+        let mut top_level: Option<usize> = None;
+        let mut not_started = 0;
+        let cut_off = self.search_with(|_, event| match event {
+            SearchEvent::Node { pending, .. } => {
+                if at_root {
+                    at_root = false;
+                    return Step::Continue;
+                }
+                // The root's children were all on the stack when the first
+                // of them was popped.
+                let total = *top_level.get_or_insert_with(|| {
+                    not_started = pending + 1;
+                    pending + 1
+                });
+                if pending < not_started {
+                    not_started = pending;
+                    // Precision is irrelevant for a progress report.
+                    #[allow(clippy::cast_precision_loss)]
+                    progress((total - not_started - 1) as f64 / total as f64);
+                }
+                Step::Continue
+            }
+            SearchEvent::Solution(option_ids) => {
+                results.push(Solution::new(option_ids.to_vec()));
+                if limit.reached(results.len()) {
+                    Step::Stop
+                } else {
+                    Step::Continue
+                }
+            }
+        });
+        if !cut_off {
+            progress(1.0);
+        }
+        results
+    }
+
+    /// Finds all solutions, each as a bitset of its option IDs.  Every bitset
+    /// is `num_options()` bits wide, with bit `i` set if option `i` is in
+    /// the solution, just like `Solution::to_bitset()`.  When a solution uses
+    /// a fair fraction of the options, this is much more compact than a list
+    /// of IDs, and the bitsets can be combined with set operations.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::sudoku_n;
+    /// use xcc::Solver;
+    ///
+    /// let matrix = sudoku_n(2, &[None; 16]);
+    /// let mut solver = Solver::new(&matrix);
+    /// let bitsets = solver.solve_all_bitsets();
+    /// assert_eq!(bitsets.len(), 288);
+    /// assert!(bitsets.iter().all(|bits| bits.len() == matrix.num_options()));
+    /// assert!(bitsets.iter().all(|bits| bits.count_ones(..) == 16));
+    /// ```
+    pub fn solve_all_bitsets(&mut self) -> Vec<FixedBitSet> {
+        let num_options = self.matrix.num_options();
+        let mut results = Vec::new();
+        self.search(|_, option_ids| {
+            let mut bits = FixedBitSet::with_capacity(num_options);
+            for option_id in option_ids {
+                bits.insert(option_id.index());
+            }
+            results.push(bits);
+            true
+        });
+        results
+    }
+
+    /// Runs the search and records the whole tree it explores, for
+    /// visualizing or teaching how the search works.  Each branch node says
+    /// which item was branched on and which option each child tried, and
+    /// each leaf is a solution or a dead end.  Branches that were never
+    /// explored because `limit` was reached are left out.
+    ///
+    /// The tree holds every node the search visits, so this is only
+    /// practical for small problems.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::toy;
+    /// use xcc::{ItemId, Limit, OptionId, SearchTree, Solver};
+    ///
+    /// let matrix = toy();
+    /// let mut solver = Solver::new(&matrix);
+    /// let tree = solver.solve_tree(Limit::All);
+    ///
+    /// // The solver branches on q, whose options are `p q x y:A` and `q x:A`.
+    /// let SearchTree::Branch { item, children } = &tree else {
+    ///     panic!("expected a branch");
+    /// };
+    /// assert_eq!(*item, ItemId::new(1));
+    /// assert_eq!(children[0].0, Some(OptionId::new(0)));
+    /// // After `p q x y:A`, r has nothing left.
+    /// assert_eq!(children[0].1, SearchTree::DeadEnd(ItemId::new(2)));
+    /// assert_eq!(children[1].0, Some(OptionId::new(3)));
+    /// // After `q x:A`, the solver branches on p, and `p r x:A y` finishes it.
+    /// assert_eq!(tree.num_nodes(), 4);
+    /// ```
+    pub fn solve_tree(&mut self, limit: Limit) -> SearchTree {
+        let mut count = 0;
+        let root = self.save_state();
+        self.grow_tree(root, Vec::new(), limit, &mut count)
+    }
+
+    /// Explores the subtree below the given state, for `solve_tree`.
+    fn grow_tree(
+        &mut self,
+        state: SavedState,
+        solution: Vec<OptionId>,
+        limit: Limit,
+        count: &mut usize,
+    ) -> SearchTree {
+        self.restore(state);
+        let mut stack = Vec::new();
+        let (item, depth) = match self.expand_node(solution, &mut stack) {
+            Node::Solution(solution) => {
+                *count += 1;
+                return SearchTree::Solution(Solution::new(solution));
+            }
+            Node::Branched { item, depth } => (item, depth),
+        };
+        if stack.is_empty() {
+            return SearchTree::DeadEnd(item);
+        }
+        let mut children = Vec::new();
+        // Visit the children in the same order as `search`, which pops them
+        // off the end of the stack.
+        while let Some((state, solution)) = stack.pop() {
+            if limit.reached(*count) {
+                break;
+            }
+            if !children.is_empty() {
+                // The previous child's subtree ended in a leaf.
+                self.log(format_args!("backtrack"));
+            }
+            let option = solution.get(depth).copied();
+            let subtree = self.grow_tree(state, solution, limit, count);
+            children.push((option, subtree));
+        }
+        children.reverse();
+        SearchTree::Branch { item, children }
+    }
+
+    /// Finds up to `n` solutions, and also reports whether the search was cut
+    /// off by the limit.  If the flag is `true`, there may be more solutions;
+    /// if it is `false`, the returned solutions are all there are.  With `n`
+    /// equal to 0, the flag says whether there are any solutions at all.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::sudoku_n;
+    /// use xcc::Solver;
+    ///
+    /// let matrix = sudoku_n(2, &[None; 16]);
+    /// let mut solver = Solver::new(&matrix);
+    /// let (solutions, more) = solver.solve_capped(10);
+    /// assert_eq!(solutions.len(), 10);
+    /// assert!(more);
+    ///
+    /// let unsolvable = sudoku_n(2, &[Some(1), Some(1), None, None].repeat(4));
+    /// let mut solver = Solver::new(&unsolvable);
+    /// assert_eq!(solver.solve_capped(0), (vec![], false));
+    /// ```
+    pub fn solve_capped(&mut self, n: usize) -> (Vec<Solution>, bool) {
+        let mut results = Vec::new();
+        if n == 0 {
+            // Any solution at all is one more than the cap.
+            let mut found = false;
+            self.search(|_, _| {
+                found = true;
+                false
+            });
+            return (results, found);
+        }
+        let cut_off = self.search(|_, option_ids| {
+            results.push(Solution::new(option_ids.to_vec()));
+            results.len() < n
+        });
+        (results, cut_off)
+    }
+
+    /// Finds up to `n` solutions whose meanings differ, returning the meanings
+    /// of each.  Two solutions count as the same if their meanings are equal
+    /// as multisets, in any order; only the first of them is kept.  Only the
+    /// distinct meaning lists are held in memory, not every raw solution.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::{Matrix, Solver};
+    ///
+    /// // Options 1 and 2 mean the same thing, so there are two raw solutions
+    /// // but only one distinct one.
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["a", "b"]);
+    /// builder.add_option("ab", ["a", "b"]);
+    /// builder.add_option("ab", ["a", "b"]);
+    /// let matrix = builder.build().unwrap();
+    ///
+    /// assert_eq!(matrix.solve_all().len(), 2);
+    /// let mut solver = Solver::new(&matrix);
+    /// assert_eq!(solver.solve_distinct_meanings(5), [[&"ab"]]);
+    /// ```
+    pub fn solve_distinct_meanings(&mut self, n: usize) -> Vec<Vec<&'a T>>
+    where
+        T: Eq + Hash,
+    {
+        let matrix = self.matrix;
+        // Meanings can't be sorted, so solutions are grouped by a hash that
+        // ignores the order of their meanings, and compared as multisets
+        // within a group.  `seen` maps each hash to indexes into `results`.
+        let hasher = hashbrown::DefaultHashBuilder::default();
+        let unordered_hash = |meanings: &[&T]| {
+            meanings
+                .iter()
+                .map(|meaning| hasher.hash_one(meaning))
+                .fold(0, u64::wrapping_add)
+        };
+        let mut seen: HashMap<u64, Vec<usize>> = HashMap::new();
+        let mut results = Vec::new();
+        if n == 0 {
+            return results;
+        }
+        self.search(|_, option_ids| {
+            let meanings: Vec<&T> = option_ids
+                .iter()
+                .map(|&option_id| &matrix.get_option(option_id).meaning)
+                .collect();
+            let group = seen.entry(unordered_hash(&meanings)).or_default();
+            if !group.iter().any(|&i| same_multiset(&results[i], &meanings)) {
+                group.push(results.len());
+                results.push(meanings);
+            }
+            results.len() < n
+        });
+        results
+    }
+
+    /// Counts solutions without building `Solution` objects, stopping once
+    /// `limit` is reached.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::toy;
+    /// use xcc::{Limit, Solver};
+    ///
+    /// let matrix = toy();
+    /// let mut solver = Solver::new(&matrix);
+    /// assert_eq!(solver.count_solutions(Limit::All), 1);
+    /// ```
+    pub fn count_solutions(&mut self, limit: Limit) -> usize {
+        self.solve_counting(limit, |_| {})
+    }
+
+    /// Like `count_solutions()`, but calls `on_found` with the running count
+    /// each time a solution turns up, for a live counter in a UI.  Returns
+    /// the final count.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::sudoku_n;
+    /// use xcc::{Limit, Solver};
+    ///
+    /// let matrix = sudoku_n(2, &[None; 16]);
+    /// let mut solver = Solver::new(&matrix);
+    /// let mut seen = Vec::new();
+    /// let total = solver.solve_counting(Limit::Max(3), |count| seen.push(count));
+    /// assert_eq!(total, 3);
+    /// assert_eq!(seen, [1, 2, 3]);
+    /// ```
+    pub fn solve_counting(&mut self, limit: Limit, mut on_found: impl FnMut(usize)) -> usize {
+        let mut count = 0;
+        self.search(|_, _| {
+            count += 1;
+            on_found(count);
+            !limit.reached(count)
+        });
+        count
+    }
+
+    /// Solves the problem, calling `f` for each solution found, along with the
+    /// colors that the solver committed to secondary items on the way to that
+    /// solution.  Stops after `limit` solutions.
+    ///
+    /// For problems like graph coloring, the committed colors are often the
+    /// answer you want, and this saves you from working them out from the
+    /// options in the solution.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::toy;
+    /// use xcc::{Color, ItemId, Limit, Solver};
+    ///
+    /// let matrix = toy();
+    /// let mut solver = Solver::new(&matrix);
+    /// solver.solve_each_with_colors(Limit::All, |_solution, colors| {
+    ///     // x is colored A, which was the first color seen by the builder.
+    ///     assert_eq!(colors.get(&ItemId::new(3)), Some(&Color::new(0)));
+    /// });
+    /// ```
+    pub fn solve_each_with_colors(
+        &mut self,
+        limit: Limit,
+        mut f: impl FnMut(&Solution, &BTreeMap<ItemId, Color>),
+    ) {
+        let mut count = 0;
+        self.search(|solver, option_ids| {
+            let solution = Solution::new(option_ids.to_vec());
+            f(&solution, &solver.committed_colors);
+            count += 1;
+            !limit.reached(count)
+        });
+    }
+
+    /// Returns an iterator over the solutions, each with the colors that the
+    /// solver committed to secondary items on the way to it, as in
+    /// `solve_each_with_colors()`.  Solutions are found lazily, one per call
+    /// to `next()`, so you can stop whenever you like.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::toy;
+    /// use xcc::{Color, ItemId, Solver};
+    ///
+    /// let matrix = toy();
+    /// let mut solver = Solver::new(&matrix);
+    /// let mut solutions = solver.iter_with_colors();
+    /// let (solution, colors) = solutions.next().unwrap();
+    /// assert_eq!(solution.meanings(&matrix), [&4, &2]);
+    /// // x is colored A, which was the first color seen by the builder.
+    /// assert_eq!(colors.get(&ItemId::new(3)), Some(&Color::new(0)));
+    /// assert!(solutions.next().is_none());
+    /// ```
+    pub fn iter_with_colors(&mut self) -> SolutionsWithColors<'_, 'a, T> {
+        let stack = vec![(self.save_state(), Vec::new())];
+        SolutionsWithColors {
+            solver: self,
+            stack,
+            resumed: false,
+        }
+    }
+
+    /// Finds a largest set of pairwise-compatible options, treating every
+    /// primary item as "at most once" instead of "exactly once".  This is
+    /// useful when no exact cover exists, but you still want the biggest
+    /// packing of options that don't overlap.
+    ///
+    /// Two options are compatible if every item they share is a secondary
+    /// item with the same color in both.  The search is branch and bound: each
+    /// option is either included or excluded, and a branch is abandoned as soon
+    /// as it can't beat the best packing found so far.
+    ///
+    /// Note that this returns option IDs rather than a `Solution`, because the
+    /// result is not an exact cover in general.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::{Matrix, Solver};
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["a", "b", "c", "d", "e"]);
+    /// builder.add_option(1, ["a", "b"]);
+    /// builder.add_option(2, ["b", "c"]);
+    /// builder.add_option(3, ["c", "d"]);
+    /// builder.add_option(4, ["d", "e", "a"]);
+    /// let matrix = builder.build().unwrap();
+    ///
+    /// // There is no exact cover, but options 1 and 3 don't overlap.
+    /// let mut solver = Solver::new(&matrix);
+    /// let packing = solver.solve_max_packing();
+    /// assert_eq!(packing.len(), 2);
+    /// ```
+    pub fn solve_max_packing(&mut self) -> Vec<OptionId> {
+        // Each option is simply in or out here, rather than chosen to cover
+        // an item, so this walks its own tree instead of using `search_with`.
+        let mut best = Vec::new();
+        let mut stack: Vec<(SavedState, Vec<OptionId>)> = vec![(self.save_state(), Vec::new())];
+
+        let mut at_leaf = false;
+        while let Some((state, mut chosen)) = stack.pop() {
+            if at_leaf {
+                self.log(format_args!("backtrack"));
+            }
+            at_leaf = true;
+            self.restore(state);
+            let remaining = self.available_options.count_ones(..);
+            if chosen.len() + remaining <= best.len() {
+                // Even taking every remaining option couldn't beat `best`.
+                continue;
+            }
+            let Some(option) = self.available_options.ones().next().map(OptionId::new) else {
+                best = chosen;
+                continue;
+            };
+            at_leaf = false;
+
+            // Branch 1: leave the option out.
+            self.available_options.set(option.index(), false);
+            let excluded = self.save_state();
+
+            // Branch 2: take the option, hiding everything incompatible with it.
+            self.commit(option);
+            self.available_options.set(option.index(), false);
+            chosen.push(option);
+            stack.push((excluded, chosen[..chosen.len() - 1].to_vec()));
+            stack.push((self.save_state(), chosen));
+        }
+
+        best
+    }
+
+    /// Searches for a cheapest solution, where the cost of a solution is the
+    /// sum of `cost` over its options.  Each time a solution strictly cheaper
+    /// than every earlier one turns up, `cb` is called with it and its cost,
+    /// so a caller can show the best answer so far while the search carries
+    /// on.  Returns the cheapest solution and its cost, or `None` if there are
+    /// no solutions.
+    ///
+    /// Partial solutions that already cost at least as much as the best
+    /// solution found are abandoned.  That pruning assumes option costs are
+    /// never negative; with negative costs the result may not be optimal.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::{Matrix, Solver};
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["a", "b"]);
+    /// builder.add_option(5, ["a", "b"]);
+    /// builder.add_option(1, ["a"]);
+    /// builder.add_option(2, ["b"]);
+    /// let matrix = builder.build().unwrap();
+    ///
+    /// let mut solver = Solver::new(&matrix);
+    /// let mut improvements = Vec::new();
+    /// let best = solver.solve_anytime_min_cost(
+    ///     |option| i64::from(matrix.get_option(option).meaning),
+    ///     |_solution, cost| improvements.push(cost),
+    /// );
+    /// let (solution, cost) = best.unwrap();
+    /// assert_eq!(cost, 3);
+    /// assert_eq!(solution.meanings(&matrix), [&1, &2]);
+    /// assert_eq!(improvements.last(), Some(&3));
+    /// ```
+    pub fn solve_anytime_min_cost(
+        &mut self,
+        cost: impl Fn(OptionId) -> i64,
+        mut cb: impl FnMut(&Solution, i64),
+    ) -> Option<(Solution, i64)> {
+        let mut best: Option<(Solution, i64)> = None;
+        let total = |option_ids: &[OptionId]| option_ids.iter().map(|&o| cost(o)).sum::<i64>();
+
+        self.search_with(|_, event| match event {
+            SearchEvent::Node { solution, .. } => {
+                if best
+                    .as_ref()
+                    .is_some_and(|(_, best_cost)| total(solution) >= *best_cost)
+                {
+                    Step::Prune
+                } else {
+                    Step::Continue
+                }
+            }
+            SearchEvent::Solution(option_ids) => {
+                let solution_cost = total(option_ids);
+                if best
+                    .as_ref()
+                    .is_none_or(|(_, best_cost)| solution_cost < *best_cost)
+                {
+                    let solution = Solution::new(option_ids.to_vec());
+                    cb(&solution, solution_cost);
+                    best = Some((solution, solution_cost));
+                }
+                Step::Continue
+            }
+        });
+
+        best
+    }
+
+    /// Finds a solution that covers as many soft primary items as possible,
+    /// or `None` if there are no solutions at all.  Every ordinary primary
+    /// item is still covered exactly once.  See
+    /// `Builder::add_soft_primary_item()`.
+    ///
+    /// The search is branch and bound: a partial solution is abandoned as
+    /// soon as it can't cover more soft items than the best solution so far,
+    /// even if it covered every soft item that still has an option left.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::{Matrix, Solver};
+    ///
+    /// // Fill two shifts; it would be nice to give Ann and Bob one each.
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["early", "late"]);
+    /// builder.add_soft_primary_item("ann");
+    /// builder.add_soft_primary_item("bob");
+    /// builder.add_option("ann early", ["early", "ann"]);
+    /// builder.add_option("bob early", ["early", "bob"]);
+    /// builder.add_option("cat late", ["late"]);
+    /// builder.add_option("bob late", ["late", "bob"]);
+    /// let matrix = builder.build().unwrap();
+    ///
+    /// let mut solver = Solver::new(&matrix);
+    /// let solution = solver.solve_max_soft().unwrap();
+    /// let mut meanings = solution.meanings(&matrix);
+    /// meanings.sort();
+    /// assert_eq!(meanings, [&"ann early", &"bob late"]);
+    /// ```
+    pub fn solve_max_soft(&mut self) -> Option<Solution> {
+        let soft_items: Vec<ItemId> = (0..self.matrix.num_items())
+            .map(ItemId::new)
+            .filter(|&item| self.matrix.is_soft(item))
+            .collect();
+        let num_covered = |matrix: &Matrix<T>, option_ids: &[OptionId]| {
+            soft_items
+                .iter()
+                .filter(|&&item| {
+                    option_ids
+                        .iter()
+                        .any(|&o| matrix.get_option(o).items.contains(item.index()))
+                })
+                .count()
+        };
 
-        match (s1, s2) {
-            (Some(s1), Some(s2)) => Unique::Ambiguous(s1, s2),
-            (Some(s1), None) => Unique::One(s1),
-            (None, Some(_)) => unreachable!(),
-            (None, None) => Unique::None,
-        }
+        let mut best: Option<(Solution, usize)> = None;
+        self.search_with(|solver, event| match event {
+            SearchEvent::Node { solution, .. } => {
+                let Some((_, best_count)) = &best else {
+                    return Step::Continue;
+                };
+                // Soft items not yet covered, but still coverable.
+                let reachable = soft_items
+                    .iter()
+                    .filter(|&&item| {
+                        solver.available_items.contains(item.index())
+                            && !solver.visible_options_for_item(item).is_empty()
+                    })
+                    .count();
+                if num_covered(solver.matrix, solution) + reachable <= *best_count {
+                    Step::Prune
+                } else {
+                    Step::Continue
+                }
+            }
+            SearchEvent::Solution(option_ids) => {
+                let count = num_covered(solver.matrix, option_ids);
+                if best
+                    .as_ref()
+                    .is_none_or(|(_, best_count)| count > *best_count)
+                {
+                    best = Some((Solution::new(option_ids.to_vec()), count));
+                    if count == soft_items.len() {
+                        return Step::Stop;
+                    }
+                }
+                Step::Continue
+            }
+        });
+        best.map(|(solution, _)| solution)
     }
 
-    /// Solves the exact cover problem represented by this matrix, returning the
-    /// first solution found.
-    pub fn solve_once(&mut self) -> Option<Solution> {
-        self.solve(Limit::Max(1)).pop()
+    /// Finds a cheapest solution, where each option's cost is worked out
+    /// from its meaning.  Returns the solution and its cost, or `None` if
+    /// there are no solutions.  This is `solve_anytime_min_cost()` for when
+    /// the cost is part of the meaning, and has the same caveat about
+    /// negative costs.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::{Matrix, Solver};
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["a", "b"]);
+    /// builder.add_option(("ab", 5), ["a", "b"]);
+    /// builder.add_option(("a", 1), ["a"]);
+    /// builder.add_option(("b", 2), ["b"]);
+    /// let matrix = builder.build().unwrap();
+    ///
+    /// let mut solver = Solver::new(&matrix);
+    /// let (solution, cost) = solver.solve_min_cost_by(|&(_, cost)| cost).unwrap();
+    /// assert_eq!(cost, 3);
+    /// assert_eq!(solution.meanings(&matrix), [&("a", 1), &("b", 2)]);
+    /// ```
+    pub fn solve_min_cost_by(&mut self, cost: impl Fn(&T) -> i64) -> Option<(Solution, i64)> {
+        let matrix = self.matrix;
+        self.solve_anytime_min_cost(|option| cost(&matrix.get_option(option).meaning), |_, _| {})
     }
 
-    /// Stack-based solver for the exact cover problem.
+    /// Finds a solution with as few options as possible, or `None` if there
+    /// are no solutions.  This is `solve_anytime_min_cost()` with every option
+    /// costing 1.
     ///
-    /// # Arguments
+    /// Solutions can differ in length whenever the options differ in size, as
+    /// in the example below.
     ///
-    /// * `max_solutions` - The maximum number of solutions to return.  If `None`,
-    ///  all solutions will be returned.
+    /// # Example
     ///
-    /// # Returns
+    /// ```
+    /// use xcc::{Matrix, Solver};
     ///
-    /// A vector of solutions.  If `max_solutions` is `None`, this will be all
-    /// solutions.  If `max_solutions` is `Some(n)`, this will be at most `n`
-    /// solutions.
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["a", "b", "c"]);
+    /// builder.add_option("a", ["a"]);
+    /// builder.add_option("b", ["b"]);
+    /// builder.add_option("c", ["c"]);
+    /// builder.add_option("bc", ["b", "c"]);
+    /// let matrix = builder.build().unwrap();
+    ///
+    /// let mut solver = Solver::new(&matrix);
+    /// let solution = solver.solve_min_options().unwrap();
+    /// let mut meanings = solution.meanings(&matrix);
+    /// meanings.sort();
+    /// assert_eq!(meanings, [&"a", &"bc"]);
+    /// ```
+    pub fn solve_min_options(&mut self) -> Option<Solution> {
+        self.solve_anytime_min_cost(|_| 1, |_, _| {})
+            .map(|(solution, _)| solution)
+    }
+
+    /// Finds solutions that use at most `max_options` options, stopping after
+    /// `limit` of them.  Branches that go over budget are abandoned as soon
+    /// as they do, so this can be much faster than filtering the results of
+    /// `solve()`.
+    ///
+    /// The budget only limits solutions; it doesn't relax what a solution
+    /// has to cover.  Primary and required items are still covered exactly.
+    /// Soft primary items, declared with `Builder::add_soft_primary_item()`,
+    /// are optional as always, so with a tight budget the solutions may
+    /// leave some of them uncovered; use `solve_max_soft()` to cover as
+    /// many as possible.
     ///
     /// # Example
     ///
     /// ```
-    /// use xcc::samples::toy;
-    /// use xcc::{Limit, Solver};
+    /// use xcc::{Limit, Matrix, Solver};
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["a", "b", "c"]);
+    /// builder.add_option("abc", ["a", "b", "c"]);
+    /// builder.add_option("ab", ["a", "b"]);
+    /// builder.add_option("a", ["a"]);
+    /// builder.add_option("b", ["b"]);
+    /// builder.add_option("c", ["c"]);
+    /// let matrix = builder.build().unwrap();
     ///
-    /// let matrix = toy();
     /// let mut solver = Solver::new(&matrix);
-    /// let solutions = solver.solve(Limit::All);
-    /// assert_eq!(solutions.len(), 1);
+    /// let solutions = solver.solve_with_budget(2, Limit::All);
+    /// assert_eq!(solutions.len(), 2);
+    /// assert!(solutions.iter().all(|solution| solution.option_ids().len() <= 2));
     /// ```
-    pub fn solve(&mut self, limit: Limit) -> Vec<Solution> {
+    pub fn solve_with_budget(&mut self, max_options: usize, limit: Limit) -> Vec<Solution> {
         let mut results = Vec::new();
-        let mut stack: Vec<(SavedState, Vec<OptionId>)> = vec![(self.save_state(), Vec::new())];
-
-        while let Some((state, mut solution)) = stack.pop() {
-            self.restore(state);
-            match self.choose_next_item() {
-                None => {
-                    // We have a solution! Decode it and add it to the results.
-                    let cells = solution.clone();
-                    results.push(Solution { option_ids: cells });
+        self.search_with(|_, event| match event {
+            SearchEvent::Node { solution, .. } if solution.len() > max_options => Step::Prune,
+            SearchEvent::Node { .. } => Step::Continue,
+            SearchEvent::Solution(option_ids) => {
+                if option_ids.len() <= max_options {
+                    results.push(Solution::new(option_ids.to_vec()));
                     if limit.reached(results.len()) {
-                        break;
+                        return Step::Stop;
                     }
                 }
-                Some(item) => {
-                    self.available_items.set(item.index(), false);
-                    let option_ids = self.cover_item_and_its_options(item);
-
-                    // We just covered some options, and now we're going to go
-                    // through them one by one, and push the resulting states
-                    // onto the stack.
-                    let ss = self.save_state();
-                    for option in option_ids {
-                        self.restore(ss.clone());
-                        self.commit(option);
-                        solution.push(option);
-                        let saved_state = self.save_state();
-                        stack.push((saved_state, solution.clone()));
-                        solution.pop();
-                    }
+                Step::Continue
+            }
+        });
+        results
+    }
+
+    /// Searches all solutions and returns the best one according to `better`,
+    /// or `None` if there are no solutions.  A solution replaces the best so
+    /// far when `better(solution, best)` returns `Ordering::Greater`, so ties
+    /// go to the solution found first.
+    ///
+    /// Unlike `solve_anytime_min_cost()`, this doesn't prune the search, so
+    /// the comparison can be anything at all.  Only the best solution is kept
+    /// in memory.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::sudoku_n;
+    /// use xcc::Solver;
+    ///
+    /// // Find the 4x4 Sudoku whose top row is lexicographically smallest.
+    /// let matrix = sudoku_n(2, &[None; 16]);
+    /// let top_row = |solution: &xcc::Solution| {
+    ///     let mut cells: Vec<_> = solution
+    ///         .meanings(&matrix)
+    ///         .into_iter()
+    ///         .filter(|&&(row, _, _)| row == 0)
+    ///         .copied()
+    ///         .collect();
+    ///     cells.sort();
+    ///     cells.into_iter().map(|(_, _, value)| value).collect::<Vec<_>>()
+    /// };
+    /// let mut solver = Solver::new(&matrix);
+    /// let best = solver.solve_best(|a, b| top_row(b).cmp(&top_row(a))).unwrap();
+    /// assert_eq!(top_row(&best), [1, 2, 3, 4]);
+    /// ```
+    pub fn solve_best(
+        &mut self,
+        better: impl Fn(&Solution, &Solution) -> Ordering,
+    ) -> Option<Solution> {
+        let mut best: Option<Solution> = None;
+        self.search(|_, option_ids| {
+            let solution = Solution::new(option_ids.to_vec());
+            if best
+                .as_ref()
+                .is_none_or(|best| better(&solution, best) == Ordering::Greater)
+            {
+                best = Some(solution);
+            }
+            true
+        });
+        best
+    }
+
+    /// Checks whether every completion of the solver's current state does the
+    /// same thing with the item `on`.  This is the question behind a hint
+    /// like "this cell must be a 5": resume the solver from the current
+    /// position with `resume()`, and ask about the cell's item.
+    ///
+    /// The search stops at the first completion that disagrees with an
+    /// earlier one.  If they all agree, it has to look at every completion.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::sudoku_n;
+    /// use xcc::{Agreement, Assignment, ItemId, Solver};
+    ///
+    /// #[rustfmt::skip]
+    /// let clues = [
+    ///     Some(1), Some(2), Some(3), None,
+    ///     None,    None,    None,    None,
+    ///     None,    None,    None,    None,
+    ///     None,    None,    None,    None,
+    /// ];
+    /// let matrix = sudoku_n(2, &clues);
+    /// let mut solver = Solver::new(&matrix);
+    ///
+    /// // The top right cell (item 3) has to be a 4...
+    /// let Agreement::Agree(Assignment::Option(option)) = solver.completions_agree(ItemId::new(3)) else {
+    ///     panic!("expected agreement");
+    /// };
+    /// assert_eq!(matrix.get_option(option).meaning, (0, 3, 4));
+    ///
+    /// // ...but the bottom right cell could be several things.
+    /// assert_eq!(solver.completions_agree(ItemId::new(15)), Agreement::Disagree);
+    /// ```
+    pub fn completions_agree(&mut self, on: ItemId) -> Agreement {
+        let start = self.save_state();
+        let mut agreed: Option<Assignment> = None;
+        let mut disagree = false;
+        self.search(|solver, option_ids| {
+            let assignment = if let Some(&color) = solver.committed_colors.get(&on) {
+                Assignment::Color(color)
+            } else {
+                option_ids
+                    .iter()
+                    .find(|option_id| {
+                        solver
+                            .matrix
+                            .get_option(**option_id)
+                            .items
+                            .contains(on.index())
+                    })
+                    .map_or(Assignment::Unused, |&option_id| {
+                        Assignment::Option(option_id)
+                    })
+            };
+            match agreed {
+                None => {
+                    agreed = Some(assignment);
+                    true
+                }
+                Some(earlier) if earlier == assignment => true,
+                Some(_) => {
+                    disagree = true;
+                    false
                 }
             }
+        });
+        self.restore(start);
+        match agreed {
+            _ if disagree => Agreement::Disagree,
+            None => Agreement::NoCompletions,
+            Some(assignment) => Agreement::Agree(assignment),
         }
+    }
 
-        results
+    /// Stack-based search engine shared by the `solve` variants.
+    ///
+    /// Calls `visit` with the solver (positioned at the solution leaf) and the
+    /// chosen option IDs each time a solution is found.  The search stops
+    /// early if `visit` returns `false`.
+    ///
+    /// Returns `true` if the search was stopped by `visit` before the whole
+    /// tree had been explored.
+    fn search(&mut self, mut visit: impl FnMut(&Self, &[OptionId]) -> bool) -> bool {
+        self.search_with(|solver, event| match event {
+            SearchEvent::Node { .. } => Step::Continue,
+            SearchEvent::Solution(option_ids) => {
+                if visit(solver, option_ids) {
+                    Step::Continue
+                } else {
+                    Step::Stop
+                }
+            }
+        })
+    }
+
+    /// Like `search`, but also calls `step` at each node before expanding
+    /// it, so that the caller can prune the node or stop the search there.
+    fn search_with(&mut self, step: impl FnMut(&Self, SearchEvent<'_>) -> Step) -> bool {
+        let mut stack = vec![(self.save_state(), Vec::new())];
+        self.search_stack(&mut stack, step)
+    }
+
+    /// Runs the search from the given stack of partial solutions, calling
+    /// `step` at each node and each solution.  See `search_with`.  If the
+    /// search stops early, the nodes it didn't get to are left on the stack,
+    /// so it can be resumed.
+    pub(crate) fn search_stack(
+        &mut self,
+        stack: &mut Vec<(SavedState, Vec<OptionId>)>,
+        mut step: impl FnMut(&Self, SearchEvent<'_>) -> Step,
+    ) -> bool {
+        let mut at_leaf = false;
+        while let Some((state, solution)) = stack.pop() {
+            if at_leaf {
+                self.log(format_args!("backtrack"));
+            }
+            self.restore(state);
+            let node = SearchEvent::Node {
+                solution: &solution,
+                pending: stack.len(),
+            };
+            match step(self, node) {
+                Step::Continue => {}
+                Step::Prune => {
+                    at_leaf = true;
+                    continue;
+                }
+                Step::Stop => return true,
+            }
+            let num_pending = stack.len();
+            if let Some(solution) = self.expand(solution, stack) {
+                // We have a solution! Hand it to the caller.
+                if step(self, SearchEvent::Solution(&solution)) == Step::Stop {
+                    return !stack.is_empty();
+                }
+            }
+            at_leaf = stack.len() == num_pending;
+        }
+        false
+    }
+
+    /// Expands one node of the search tree, starting from the current state.
+    /// If the node is a solution, returns it.  Otherwise, pushes the node's
+    /// children onto `stack`.
+    pub(crate) fn expand(
+        &mut self,
+        solution: Vec<OptionId>,
+        stack: &mut Vec<(SavedState, Vec<OptionId>)>,
+    ) -> Option<Vec<OptionId>> {
+        match self.expand_node(solution, stack) {
+            Node::Solution(solution) => Some(solution),
+            Node::Branched { .. } => None,
+        }
+    }
+
+    /// Does the work of `expand`, also saying which item was branched on.
+    fn expand_node(
+        &mut self,
+        mut solution: Vec<OptionId>,
+        stack: &mut Vec<(SavedState, Vec<OptionId>)>,
+    ) -> Node {
+        if self.propagation {
+            self.propagate(&mut solution);
+        }
+        let Some(item) = self.choose_next_item() else {
+            return Node::Solution(solution);
+        };
+        let depth = solution.len();
+        // Several options may share a secondary item, so for those, each
+        // branch says which option is the first to use it.
+        let secondary = !self.matrix.is_primary(item);
+        let optional = secondary && !self.matrix.is_required(item);
+        self.log(format_args!("branch on item {}", item.index()));
+        let mut option_ids = self.branch_options(item);
+        if !self.color_preferences.is_empty() {
+            // Branches are explored from the end of the list, so put the most
+            // preferred options last.
+            option_ids
+                .sort_by_cached_key(|&option| core::cmp::Reverse(self.preference_rank(option)));
+        }
+
+        // We just covered some options, and now we're going to go through
+        // them one by one, and push the resulting states onto the stack.
+        let ss = self.save_state();
+        for (i, &option) in option_ids.iter().enumerate() {
+            self.restore(ss.clone());
+            if secondary {
+                // Any solution that uses one of the earlier options for this
+                // item was found on an earlier branch.
+                for earlier in &option_ids[..i] {
+                    self.available_options.set(earlier.index(), false);
+                }
+            }
+            self.commit(option);
+            solution.push(option);
+            let saved_state = self.save_state();
+            stack.push((saved_state, solution.clone()));
+            solution.pop();
+        }
+        if optional {
+            // The last branch leaves the item unused.
+            self.restore(ss);
+            self.cover_item_and_its_options(item);
+            stack.push((self.save_state(), solution));
+        }
+        Node::Branched { item, depth }
+    }
+
+    /// Prepares to branch on an item, returning the options to try.
+    fn branch_options(&mut self, item: ItemId) -> Vec<OptionId> {
+        if self.matrix.is_primary(item) {
+            self.available_items.set(item.index(), false);
+            self.cover_item_and_its_options(item)
+        } else {
+            // A secondary item can be shared by several options (if they agree
+            // on its color), so leave its options visible and let `commit`
+            // purify them.
+            self.visible_options_for_item(item)
+        }
+    }
+
+    /// Commits to forced options, one after another, for as long as some
+    /// uncovered item has exactly one option left.
+    fn propagate(&mut self, solution: &mut Vec<OptionId>) {
+        while let Some((item, 1)) = self.choose_next_item_with_count() {
+            let option_ids = self.branch_options(item);
+            self.commit(option_ids[0]);
+            solution.push(option_ids[0]);
+        }
     }
 
     /// Makes a provisional commitment to an option.
     fn commit(&mut self, option_id: OptionId) {
+        self.log(format_args!("commit option {}", option_id.index()));
+        // A colored primary item is covered by `branch_options` before we get
+        // here, but its color still needs recording.
+        for (item, color) in self.matrix.items_for_option(option_id) {
+            if let (true, Some(color)) = (self.matrix.is_primary(item), color) {
+                self.committed_colors.insert(item, color);
+            }
+        }
         let items: Vec<_> = self
             .matrix
             .items_for_option(option_id)
@@ -141,17 +1684,85 @@ impl<'a, T> Solver<'a, T> {
             .collect();
         for (item, color) in items {
             match color {
-                None => {
-                    self.cover_item_and_its_options(item);
+                None if self.is_free(item) => continue,
+                _ if self.color_policy.is_some() && !self.matrix.is_primary(item) => {
+                    if !self.committed_colors.contains_key(&item) {
+                        self.apply_color_policy(item, color);
+                    }
                 }
-                Some(color) => {
+                Some(color) if !self.matrix.is_primary(item) => {
                     if !self.committed_colors.contains_key(&item) {
                         self.purify(item, color);
                     }
                 }
+                _ => {
+                    self.cover_item_and_its_options(item);
+                }
             }
             self.available_items.set(item.index(), false);
         }
+        for &(item, color) in &self.matrix.get_option(option_id).forbidden {
+            self.forbid(item, color);
+        }
+    }
+
+    /// Like `purify`, but asks the color policy what to do with each option
+    /// that uses the item.
+    fn apply_color_policy(&mut self, item: ItemId, item_color: Option<Color>) {
+        match item_color {
+            Some(color) => self.log(format_args!(
+                "apply color policy to item {} with color {}",
+                item.index(),
+                color.index()
+            )),
+            None => self.log(format_args!(
+                "apply color policy to item {} without a color",
+                item.index()
+            )),
+        }
+        let Some(policy) = &self.color_policy else {
+            return;
+        };
+        for option in self.matrix.options_for_item(item) {
+            match policy.compatible(item_color, option.color_of(item)) {
+                Commit::Keep => {}
+                Commit::Commit => {
+                    if let Some(color) = item_color {
+                        self.committed_colors.insert(item, color);
+                    }
+                }
+                Commit::Hide => {
+                    self.available_options.set(option.option_id.index(), false);
+                }
+            }
+        }
+        if let Some(color) = item_color {
+            self.hide_options_forbidding(item, color);
+        }
+    }
+
+    /// Hides the visible options that give `item` the color `color`, after
+    /// committing to an option that forbids it.
+    fn forbid(&mut self, item: ItemId, color: Color) {
+        self.log(format_args!(
+            "forbid item {} from color {}",
+            item.index(),
+            color.index()
+        ));
+        for option in self.matrix.options_for_item(item) {
+            if option.color_of(item) == Some(color) {
+                self.available_options.set(option.option_id.index(), false);
+            }
+        }
+    }
+
+    /// Returns the IDs of the visible options containing a given item.
+    fn visible_options_for_item(&self, item: ItemId) -> Vec<OptionId> {
+        self.matrix
+            .options_for_item(item)
+            .map(|option| option.option_id)
+            .filter(|option_id| self.available_options.contains(option_id.index()))
+            .collect()
     }
 
     /// Hide all visible options containing a given item, and return the option IDs.
@@ -164,6 +1775,11 @@ impl<'a, T> Solver<'a, T> {
             }
         }
         self.available_items.set(item_num.index(), false);
+        self.log(format_args!(
+            "cover item {} ({} options hidden)",
+            item_num.index(),
+            covered_options.len()
+        ));
         covered_options
     }
 
@@ -174,25 +1790,81 @@ impl<'a, T> Solver<'a, T> {
     /// This method is called during the commit process when the solver first assigns a color
     /// to a secondary item.
     fn purify(&mut self, item_num: ItemId, item_color: Color) {
+        self.log(format_args!(
+            "purify item {} to color {}",
+            item_num.index(),
+            item_color.index()
+        ));
         for option in self.matrix.options_for_item(item_num) {
-            if option.colors.get(&item_num) == Some(&item_color) {
-                self.committed_colors.insert(item_num, item_color);
-            } else {
-                self.available_options.set(option.option_id.index(), false);
+            match option.color_of(item_num) {
+                Some(color) if color == item_color => {
+                    self.committed_colors.insert(item_num, item_color);
+                }
+                None if self.is_free(item_num) => {}
+                _ => {
+                    self.available_options.set(option.option_id.index(), false);
+                }
             }
         }
+        self.hide_options_forbidding(item_num, item_color);
+    }
+
+    /// Hides the options that forbid `item` from having the color `color`,
+    /// once the item has been given that color.
+    fn hide_options_forbidding(&mut self, item: ItemId, color: Color) {
+        for option_id in self.matrix.options_forbidding(item, color) {
+            self.available_options.set(option_id.index(), false);
+        }
+    }
+
+    /// Returns `true` if `item` is a secondary item that places no constraint
+    /// on the solution when it appears without a color.
+    fn is_free(&self, item: ItemId) -> bool {
+        self.matrix.secondary_mode() == SecondaryMode::Free
+            && !self.matrix.is_primary(item)
+            && !self.matrix.is_required(item)
+            && !self.matrix.is_soft(item)
     }
 
-    /// Finds the uncovered primary item with the fewest remaining options, and
-    /// returns its index.
+    /// Finds the uncovered primary (or required) item with the fewest
+    /// remaining options, and returns its index.  With secondary branching,
+    /// secondary items are candidates too.  Returns `None` once every primary
+    /// and required item is covered.
     #[must_use]
     fn choose_next_item(&self) -> Option<ItemId> {
+        self.choose_next_item_with_count().map(|(item, _)| item)
+    }
+
+    /// Like `choose_next_item`, but also returns the number of branches for
+    /// the chosen item.
+    fn choose_next_item_with_count(&self) -> Option<(ItemId, usize)> {
+        let must_cover = |item| self.matrix.is_primary(item) || self.matrix.is_required(item);
+        if !self.available_items.ones().map(ItemId::new).any(must_cover) {
+            return None;
+        }
         let item_counts = self.count_items();
         self.available_items
             .ones()
-            .take_while(|&i| i < self.matrix.num_primary_items())
-            .min_by_key(|&i| item_counts[i])
-            .map(ItemId::new)
+            .filter_map(|i| {
+                let item = ItemId::new(i);
+                if must_cover(item) {
+                    Some((item, item_counts[i]))
+                } else if self.secondary_branching && !self.is_free(item) && item_counts[i] > 0 {
+                    Some((item, item_counts[i] + 1))
+                } else {
+                    None
+                }
+            })
+            .min_by(|&(a, a_count), &(b, b_count)| match &self.item_weights {
+                None => a_count.cmp(&b_count),
+                Some(weights) => {
+                    // Counts are far too small for the conversion to lose
+                    // precision.
+                    #[allow(clippy::cast_precision_loss)]
+                    let score = |item: ItemId, count: usize| count as f64 / weights[item.index()];
+                    score(a, a_count).total_cmp(&score(b, b_count))
+                }
+            })
     }
 
     /// Counts the number of available options for each available item.
@@ -201,6 +1873,9 @@ impl<'a, T> Solver<'a, T> {
     fn count_items(&self) -> Vec<usize> {
         let mut item_counts = vec![0; self.matrix.num_items()];
         for option in self.available_options.ones().map(OptionId::new) {
+            if self.color_aware_counts && self.conflicts_with_committed_colors(option) {
+                continue;
+            }
             for (item, _) in self.matrix.items_for_option(option) {
                 item_counts[item.index()] += 1;
             }
@@ -208,6 +1883,21 @@ impl<'a, T> Solver<'a, T> {
         item_counts
     }
 
+    /// Returns true if the option uses an item that has a committed color,
+    /// but with a different color (or none).  An uncolored use of a free
+    /// item never conflicts.
+    fn conflicts_with_committed_colors(&self, option_id: OptionId) -> bool {
+        let option = self.matrix.get_option(option_id);
+        option.colored_items().any(|(item, color)| {
+            if color.is_none() && self.is_free(item) {
+                return false;
+            }
+            self.committed_colors
+                .get(&item)
+                .is_some_and(|&committed| color != Some(committed))
+        })
+    }
+
     fn save_state(&self) -> SavedState {
         SavedState {
             available_items: self.available_items.clone(),
@@ -223,6 +1913,221 @@ impl<'a, T> Solver<'a, T> {
     }
 }
 
+/// Answers `solve_unique()` for the same matrix with different sets of
+/// options disabled, reusing one solver between calls.  Each check still
+/// searches from scratch, but skips setting up a new solver, which adds up
+/// in loops like clue minimization that check thousands of times.
+///
+/// # Example
+///
+/// ```
+/// use xcc::samples::sudoku_n;
+/// use xcc::{OptionId, UniquenessChecker};
+///
+/// let matrix = sudoku_n(2, &[None; 16]);
+/// let mut checker = UniquenessChecker::new(&matrix);
+/// assert!(checker.check(&[]).is_ambiguous());
+///
+/// // Disabling every option but one solution's leaves that solution.
+/// let solution = matrix.solve_once().unwrap();
+/// let others: Vec<OptionId> = (0..matrix.num_options())
+///     .map(OptionId::new)
+///     .filter(|&id| !solution.option_ids().contains(&id))
+///     .collect();
+/// assert_eq!(checker.check(&others).unique(), Some(&solution));
+/// ```
+pub struct UniquenessChecker<'a, T> {
+    solver: Solver<'a, T>,
+}
+
+impl<'a, T> UniquenessChecker<'a, T> {
+    /// Creates a checker for the given matrix.
+    #[must_use]
+    pub fn new(matrix: &'a Matrix<T>) -> Self {
+        Self {
+            solver: Solver::new(matrix),
+        }
+    }
+
+    /// Returns the solver that runs the checks, to change its settings.
+    /// Those settings are kept from one check to the next.
+    pub fn solver_mut(&mut self) -> &mut Solver<'a, T> {
+        &mut self.solver
+    }
+
+    /// Solves the matrix with the given options disabled, returning the
+    /// unique solution if there is exactly one.  Options disabled by an
+    /// earlier check are available again.
+    pub fn check(&mut self, disabled: &[OptionId]) -> Unique<Solution> {
+        self.solver.reset();
+        self.solver.with_disabled(disabled);
+        self.solver.solve_unique()
+    }
+}
+
+/// An iterator over solutions and their committed colors, returned by
+/// `Solver::iter_with_colors()`.
+pub struct SolutionsWithColors<'s, 'a, T> {
+    solver: &'s mut Solver<'a, T>,
+    stack: Vec<(SavedState, Vec<OptionId>)>,
+    /// Whether the last call returned a solution, so that the search backs
+    /// up from it when it resumes.
+    resumed: bool,
+}
+
+impl<T> Iterator for SolutionsWithColors<'_, '_, T> {
+    type Item = (Solution, BTreeMap<ItemId, Color>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.resumed && !self.stack.is_empty() {
+            self.solver.log(format_args!("backtrack"));
+        }
+        let mut found = None;
+        self.solver
+            .search_stack(&mut self.stack, |solver, event| match event {
+                SearchEvent::Node { .. } => Step::Continue,
+                SearchEvent::Solution(option_ids) => {
+                    let colors = solver.committed_colors.clone();
+                    found = Some((Solution::new(option_ids.to_vec()), colors));
+                    Step::Stop
+                }
+            });
+        self.resumed = found.is_some();
+        found
+    }
+}
+
+/// What `Solver::search_stack` tells its caller about.
+pub(crate) enum SearchEvent<'s> {
+    /// The search is about to expand a node with this partial solution.  The
+    /// solver is in the node's state, and `pending` other nodes are waiting
+    /// on the stack.
+    Node {
+        solution: &'s [OptionId],
+        pending: usize,
+    },
+    /// The search found a solution.
+    Solution(&'s [OptionId]),
+}
+
+/// What `Solver::search_stack` should do after telling its caller about a
+/// `SearchEvent`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Step {
+    /// Carry on searching.
+    Continue,
+    /// Skip this node and everything below it.  This is the same as
+    /// `Continue` for a solution.
+    Prune,
+    /// Stop the search.
+    Stop,
+}
+
+/// What `Solver::expand_node` found.
+enum Node {
+    /// Every primary item is covered.
+    Solution(Vec<OptionId>),
+    /// The node's children are on the stack.  `depth` is the length of the
+    /// partial solution, after any forced options, before each child adds
+    /// its own option.
+    Branched { item: ItemId, depth: usize },
+}
+
+/// The tree explored by a search, as returned by `Solver::solve_tree()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SearchTree {
+    /// A complete solution.
+    Solution(Solution),
+    /// The solver chose to branch on this item, but it had no options left.
+    DeadEnd(ItemId),
+    /// The solver branched on `item`.  Each child says which option it
+    /// tried, or `None` for the branch where an optional secondary item is
+    /// left unused.
+    Branch {
+        /// The item branched on.
+        item: ItemId,
+        /// The option tried by each child, and the subtree below it.
+        children: Vec<(Option<OptionId>, SearchTree)>,
+    },
+}
+
+impl SearchTree {
+    /// Returns the number of nodes in the tree, including the root.
+    #[must_use]
+    pub fn num_nodes(&self) -> usize {
+        match self {
+            SearchTree::Solution(_) | SearchTree::DeadEnd(_) => 1,
+            SearchTree::Branch { children, .. } => {
+                1 + children
+                    .iter()
+                    .map(|(_, child)| child.num_nodes())
+                    .sum::<usize>()
+            }
+        }
+    }
+}
+
+/// Returns `true` if `a` and `b` hold the same values, the same number of
+/// times each.
+fn same_multiset<T: Eq + Hash>(a: &[&T], b: &[&T]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut counts: HashMap<&T, isize> = HashMap::new();
+    for &value in a {
+        *counts.entry(value).or_default() += 1;
+    }
+    for &value in b {
+        *counts.entry(value).or_default() -= 1;
+    }
+    counts.values().all(|&count| count == 0)
+}
+
+/// Turns the first two solutions found by a search into a `Unique`.
+fn to_unique(mut solutions: Vec<Solution>) -> Unique<Solution> {
+    let s1 = solutions.pop();
+    let s2 = solutions.pop();
+
+    match (s1, s2) {
+        (Some(s1), Some(s2)) => Unique::Ambiguous(s1, s2),
+        (Some(s1), None) => Unique::One(s1),
+        (None, Some(_)) => unreachable!(),
+        (None, None) => Unique::None,
+    }
+}
+
+/// An error returned by `Solver::try_solve()` for a matrix that there is
+/// no point in solving.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, thiserror::Error)]
+pub enum SolveError {
+    /// The matrix has no primary or required items, so the empty set of
+    /// options is trivially a solution.
+    #[error("The matrix has no primary items")]
+    NoPrimaryItems,
+    /// The matrix has no options, so it can't have any solutions.
+    #[error("The matrix has no options")]
+    NoOptions,
+}
+
+/// The error returned by `Solver::commit_color()` when the item already has
+/// a different color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, thiserror::Error)]
+#[error("item {} is already colored {}, not {}", item.index(), committed.index(), requested.index())]
+pub struct Conflict {
+    /// The item being colored.
+    pub item: ItemId,
+    /// The color the item already has.
+    pub committed: Color,
+    /// The color that was asked for.
+    pub requested: Color,
+}
+
+/// The error returned when a search runs out of time, as with
+/// `Solver::solve_unique_timed()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, thiserror::Error)]
+#[error("the search ran out of time")]
+pub struct Timeout;
+
 /// A limit on the number of solutions to return. This is used by
 /// `Matrix::solve()`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -243,6 +2148,44 @@ impl Limit {
     }
 }
 
+/// What a solution does with a particular item.  See
+/// `Solver::completions_agree()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Assignment {
+    /// The item is covered by this option, without a color.
+    Option(OptionId),
+    /// The item is a secondary item with this color.
+    Color(Color),
+    /// The item is a secondary item that no option in the solution uses.
+    Unused,
+}
+
+/// The result of `Solver::completions_agree()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Agreement {
+    /// There are no completions at all.
+    NoCompletions,
+    /// Every completion does the same thing with the item.
+    Agree(Assignment),
+    /// At least two completions do different things with the item.
+    Disagree,
+}
+
+/// Why an option can or can't be chosen.  See `Solver::explain_option()`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OptionStatus {
+    /// The option can be chosen.
+    Available,
+    /// The option uses this item, which has already been covered.
+    Covered(ItemId),
+    /// The option gives this secondary item a color (or no color), but a
+    /// different color has been committed to it.
+    ColorConflict(ItemId, Option<Color>, Color),
+    /// None of the option's items rule it out, but the search has set it
+    /// aside, for example because an earlier branch already tried it.
+    Excluded,
+}
+
 /// A solution to an exact cover problem.
 ///
 /// The usual way to use this is to call `Matrix::solve_all()`, then for each
@@ -251,16 +2194,53 @@ impl Limit {
 /// # Example
 ///
 /// ```
-/// let mut toy = xcc::samples::toy();
+/// let toy = xcc::samples::toy();
 /// toy.solve_all().into_iter().for_each(|solution| {
 ///    println!("Solution: {:?}", solution.meanings(&toy));
 /// });
 /// ```
-#[derive(Debug, Clone, PartialEq, Eq)]
+///
+/// # Equality and hashing
+///
+/// A solution is a *set* of options, so two solutions are equal if they
+/// contain the same options, even if the solver found them in a different
+/// order.  Hashing is consistent with this, so you can deduplicate solutions
+/// from separate runs with a `HashSet`.
+#[derive(Debug, Clone)]
 pub struct Solution {
     option_ids: Vec<OptionId>,
 }
 
+impl Solution {
+    /// Creates a solution from the IDs of its options.
+    pub(crate) fn new(option_ids: Vec<OptionId>) -> Self {
+        Solution { option_ids }
+    }
+
+    /// Returns the option IDs in ascending order, for order-insensitive
+    /// comparison and hashing.
+    fn sorted_option_ids(&self) -> Vec<OptionId> {
+        let mut option_ids = self.option_ids.clone();
+        option_ids.sort_unstable();
+        option_ids
+    }
+}
+
+impl PartialEq for Solution {
+    fn eq(&self, other: &Self) -> bool {
+        self.option_ids.len() == other.option_ids.len()
+            && self.sorted_option_ids() == other.sorted_option_ids()
+    }
+}
+
+impl Eq for Solution {}
+
+impl Hash for Solution {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.sorted_option_ids().hash(state);
+    }
+}
+
 impl Solution {
     /// Returns the meanings of the options in this solution.  The meanings
     /// come from the parameter to `Builder::add_option()`.
@@ -277,7 +2257,7 @@ impl Solution {
     /// builder.add_option("option three", ["p", "x:B"]);
     /// builder.add_option("option four", ["q", "x:A"]);
     /// builder.add_option("option five", ["r", "y:B"]);
-    /// let mut matrix = builder.build().expect("could not build matrix");
+    /// let matrix = builder.build().expect("could not build matrix");
     /// let solution = matrix.solve_all().pop().unwrap();
     /// assert_eq!(solution.meanings(&matrix), [&"option four", &"option two"]);
     /// ```
@@ -288,17 +2268,234 @@ impl Solution {
             .map(|&i| &matrix.get_option(i).meaning)
             .collect()
     }
+
+    /// Returns the IDs of the options in this solution, in the order the
+    /// solver chose them.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::sudoku_n;
+    ///
+    /// let matrix = sudoku_n(2, &[None; 16]);
+    /// let solution = matrix.solve_once().unwrap();
+    /// assert_eq!(solution.option_ids().len(), 16);
+    ///
+    /// // Iterating over a solution gives the same IDs.
+    /// let ids: Vec<_> = solution.into_iter().collect();
+    /// assert_eq!(ids, solution.option_ids());
+    /// ```
+    #[must_use]
+    pub fn option_ids(&self) -> &[OptionId] {
+        &self.option_ids
+    }
+
+    /// Returns an iterator over the IDs of the options in this solution.
+    pub fn iter(&self) -> iter::Copied<slice::Iter<'_, OptionId>> {
+        self.option_ids.iter().copied()
+    }
+
+    /// Returns `true` if every one of the `forced` options is part of this
+    /// solution, such as the clues of a puzzle that a cached solution is
+    /// supposed to answer.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::toy;
+    /// use xcc::OptionId;
+    ///
+    /// let solution = toy().solve_once().unwrap();
+    /// assert!(solution.respects(&[OptionId::new(3)]));
+    /// assert!(!solution.respects(&[OptionId::new(1), OptionId::new(2)]));
+    /// assert!(solution.respects(&[]));
+    /// ```
+    #[must_use]
+    pub fn respects(&self, forced: &[OptionId]) -> bool {
+        forced
+            .iter()
+            .all(|option_id| self.option_ids.contains(option_id))
+    }
+
+    /// Translates a solution of `old` into the same solution of `new`, for
+    /// when a matrix has been rebuilt and its options may have moved.  Each
+    /// option is matched by its items and colors, ignoring its meaning, so
+    /// both matrices need the same items in the same order.  Returns `None`
+    /// if some option of the solution has no match in `new`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::Matrix;
+    ///
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["a", "b"]);
+    /// builder.add_option("ab", ["a", "b"]);
+    /// builder.add_option("a", ["a"]);
+    /// builder.add_option("b", ["b"]);
+    /// let old = builder.build().unwrap();
+    /// let solutions = old.solve_all();
+    /// let solution = solutions.iter().find(|s| s.option_ids().len() == 2).unwrap();
+    /// assert_eq!(solution.meanings(&old), [&"a", &"b"]);
+    ///
+    /// // Rebuild with the options in a different order.
+    /// let mut builder = Matrix::builder();
+    /// builder.add_primary_items(["a", "b"]);
+    /// builder.add_option("b", ["b"]);
+    /// builder.add_option("a", ["a"]);
+    /// let new = builder.build().unwrap();
+    /// let remapped = solution.remap(&old, &new).unwrap();
+    /// assert_eq!(remapped.meanings(&new), [&"a", &"b"]);
+    /// ```
+    #[must_use]
+    pub fn remap<T, U>(&self, old: &Matrix<T>, new: &Matrix<U>) -> Option<Solution> {
+        let mut by_signature = HashMap::new();
+        for option_id in (0..new.num_options()).rev().map(OptionId::new) {
+            by_signature.insert(new.get_option(option_id).signature(), option_id);
+        }
+        self.option_ids
+            .iter()
+            .map(|&option_id| {
+                by_signature
+                    .get(&old.get_option(option_id).signature())
+                    .copied()
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(Solution::new)
+    }
+
+    /// Returns the options in this solution as a bitset, with bit `i` set if
+    /// option `i` is part of the solution.  `num_options` should be the
+    /// matrix's `num_options()`, so that bitsets from different solutions
+    /// line up and can be combined with bitwise operations.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the solution uses an option ID of `num_options` or more.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::samples::sudoku_n;
+    /// use xcc::Solution;
+    ///
+    /// let matrix = sudoku_n(2, &[None; 16]);
+    /// let solutions = matrix.solve_all();
+    /// let mut common = solutions[0].to_bitset(matrix.num_options());
+    /// for solution in &solutions[1..] {
+    ///     common.intersect_with(&solution.to_bitset(matrix.num_options()));
+    /// }
+    /// // No option is used by every 4x4 Sudoku.
+    /// assert_eq!(common.count_ones(..), 0);
+    ///
+    /// let first = Solution::from_bitset(&solutions[0].to_bitset(matrix.num_options()));
+    /// assert_eq!(first, solutions[0]);
+    /// ```
+    #[must_use]
+    pub fn to_bitset(&self, num_options: usize) -> FixedBitSet {
+        let mut bits = FixedBitSet::with_capacity(num_options);
+        for option_id in &self.option_ids {
+            bits.insert(option_id.index());
+        }
+        bits
+    }
+
+    /// Creates a solution from a bitset of option IDs, as produced by
+    /// `to_bitset()`.  The options are listed in ascending order of ID.
+    #[must_use]
+    pub fn from_bitset(bits: &FixedBitSet) -> Solution {
+        Solution::new(bits.ones().map(OptionId::new).collect())
+    }
+}
+
+impl<'a> IntoIterator for &'a Solution {
+    type Item = OptionId;
+    type IntoIter = iter::Copied<slice::Iter<'a, OptionId>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
 }
 
+/// A snapshot of the solver's position in the search: which items and
+/// options are still available, and which colors have been committed to
+/// secondary items.
+///
+/// Get one from `Solver::snapshot()` and turn it back into a solver with
+/// `Solver::resume()`.  With the `serde` feature, it can be serialized, so a
+/// long search can be checkpointed and picked up in another process.
+///
+/// # Example
+///
+/// ```
+/// use std::collections::BTreeMap;
+/// use xcc::samples::sudoku_n;
+/// use xcc::{FixedBitSet, Limit, SavedState, Solver};
+///
+/// let matrix = sudoku_n(2, &[None; 16]);
+/// let snapshot = Solver::new(&matrix).snapshot();
+///
+/// // Start with everything available, as a new solver does.
+/// let mut items = FixedBitSet::with_capacity(matrix.num_items());
+/// items.insert_range(..);
+/// let mut options = FixedBitSet::with_capacity(matrix.num_options());
+/// options.insert_range(..);
+/// assert_eq!(snapshot.available_items(), &items);
+/// assert_eq!(snapshot.available_options(), &options);
+///
+/// // Forbid the first option, which puts a 1 in the top left corner.
+/// options.set(0, false);
+/// let state = SavedState::new(items, options, BTreeMap::new());
+///
+/// let mut solver = Solver::resume(&matrix, state);
+/// assert_eq!(solver.count_solutions(Limit::All), 288 - 72);
+/// ```
 #[derive(Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct SavedState {
     available_items: FixedBitSet,
     available_options: FixedBitSet,
-    known_correct: HashMap<ItemId, Color>,
+    known_correct: BTreeMap<ItemId, Color>,
+}
+
+impl SavedState {
+    /// Creates a state from its parts.  Bit `i` of `available_items` is set if
+    /// item `i` is still to be covered, and bit `j` of `available_options` is
+    /// set if option `j` can still be chosen.
+    #[must_use]
+    pub fn new(
+        available_items: FixedBitSet,
+        available_options: FixedBitSet,
+        committed_colors: BTreeMap<ItemId, Color>,
+    ) -> Self {
+        SavedState {
+            available_items,
+            available_options,
+            known_correct: committed_colors,
+        }
+    }
+
+    /// Returns the items that have not been covered yet.
+    #[must_use]
+    pub fn available_items(&self) -> &FixedBitSet {
+        &self.available_items
+    }
+
+    /// Returns the options that can still be chosen.
+    #[must_use]
+    pub fn available_options(&self) -> &FixedBitSet {
+        &self.available_options
+    }
+
+    /// Returns the colors committed to secondary items so far.
+    #[must_use]
+    pub fn committed_colors(&self) -> &BTreeMap<ItemId, Color> {
+        &self.known_correct
+    }
 }
 
-impl std::fmt::Debug for SavedState {
-    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+impl core::fmt::Debug for SavedState {
+    fn fmt(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
         let available_items = self.available_items.ones().collect::<Vec<_>>();
         let available_options = self.available_options.ones().collect::<Vec<_>>();
         f.debug_struct("SavedState")
@@ -312,6 +2509,9 @@ impl std::fmt::Debug for SavedState {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::collections::HashSet;
+    use crate::ColoredItem;
+    use alloc::format;
 
     #[test]
     fn test_choose_next_item() {
@@ -332,6 +2532,119 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_explain_option() {
+        let matrix = crate::samples::toy();
+        let mut solver = Solver::new(&matrix);
+        // "q x:A" covers q and colors x with A.
+        solver.commit(OptionId::new(3));
+        let (q, x) = (ItemId::new(1), ItemId::new(3));
+        let a = matrix.get_option(OptionId::new(3)).color_of(x).unwrap();
+        let b = matrix.get_option(OptionId::new(2)).color_of(x).unwrap();
+
+        // "p q x y:A"
+        assert_eq!(
+            solver.explain_option(OptionId::new(0)),
+            OptionStatus::Covered(q)
+        );
+        // "p r x:A y"
+        assert_eq!(
+            solver.explain_option(OptionId::new(1)),
+            OptionStatus::Available
+        );
+        // "p x:B"
+        assert_eq!(
+            solver.explain_option(OptionId::new(2)),
+            OptionStatus::ColorConflict(x, Some(b), a)
+        );
+        // "q x:A" itself
+        assert_eq!(
+            solver.explain_option(OptionId::new(3)),
+            OptionStatus::Covered(q)
+        );
+    }
+
+    fn count_tree_solutions(tree: &SearchTree) -> usize {
+        match tree {
+            SearchTree::Solution(_) => 1,
+            SearchTree::DeadEnd(_) => 0,
+            SearchTree::Branch { children, .. } => children
+                .iter()
+                .map(|(_, child)| count_tree_solutions(child))
+                .sum(),
+        }
+    }
+
+    #[test]
+    fn test_solve_tree_finds_every_solution() {
+        let matrix = crate::samples::sudoku_n(2, &[None; 16]);
+        let tree = Solver::new(&matrix).solve_tree(Limit::All);
+        assert_eq!(count_tree_solutions(&tree), 288);
+        let tree = Solver::new(&matrix).solve_tree(Limit::Max(5));
+        assert_eq!(count_tree_solutions(&tree), 5);
+
+        let matrix = crate::samples::word_fill(&["..", ".."], &["AB", "BA", "AA"]);
+        let mut solver = Solver::new(&matrix);
+        let expected = solver.count_solutions(Limit::All);
+        let tree = Solver::new(&matrix).solve_tree(Limit::All);
+        assert_eq!(count_tree_solutions(&tree), expected);
+    }
+
+    #[test]
+    fn test_color_preference_keeps_solutions() {
+        let matrix = crate::samples::word_fill(&["..", ".."], &["AB", "BA", "AA", "BB"]);
+        let expected: HashSet<Solution> = Solver::new(&matrix).solve_all().into_iter().collect();
+        for i in 0..matrix.num_items() {
+            let item = ItemId::new(i);
+            let mut colors = matrix.colors_for_item(item);
+            if colors.is_empty() {
+                continue;
+            }
+            colors.reverse();
+            let mut solver = Solver::new(&matrix);
+            solver.set_color_preference(item, colors);
+            let solutions = solver.solve_all();
+            assert_eq!(solutions.len(), expected.len());
+            assert_eq!(solutions.into_iter().collect::<HashSet<_>>(), expected);
+        }
+    }
+
+    #[test]
+    fn test_item_weights() {
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["a", "b", "c", "d"]);
+        builder.add_option(1, ["a", "b"]);
+        builder.add_option(2, ["a", "c"]);
+        builder.add_option(3, ["a", "d"]);
+        builder.add_option(4, ["b", "d"]);
+        let matrix = builder.build().unwrap();
+
+        // a has 3 options, so 3 / 4.0 beats c's 1 / 1.0.
+        let mut solver = Solver::new(&matrix);
+        solver.set_item_weights(HashMap::from([(ItemId::new(0), 4.0)]));
+        assert_eq!(solver.choose_next_item(), Some(ItemId::new(0)));
+
+        // With b and d tied at 2 / 1.0, the lower ID wins.
+        solver.set_item_weights(HashMap::from([(ItemId::new(2), 0.25)]));
+        assert_eq!(solver.choose_next_item(), Some(ItemId::new(1)));
+    }
+
+    #[test]
+    #[should_panic(expected = "weights must be finite and positive")]
+    fn test_item_weights_must_be_positive() {
+        let matrix = crate::samples::toy();
+        let mut solver = Solver::new(&matrix);
+        solver.set_item_weights(HashMap::from([(ItemId::new(0), 0.0)]));
+    }
+
+    #[test]
+    #[should_panic(expected = "is not in the matrix")]
+    fn test_item_weights_must_name_items_in_the_matrix() {
+        let matrix = crate::samples::toy();
+        let mut solver = Solver::new(&matrix);
+        solver.set_item_weights(HashMap::from([(ItemId::new(99), 1.0)]));
+    }
+
     #[test]
     fn test_simple_solve() {
         let mut builder = Matrix::builder();
@@ -340,7 +2653,7 @@ mod tests {
         builder.add_option(1, ["a"]);
         builder.add_option(2, ["b"]);
 
-        let mut matrix = builder.build().unwrap();
+        let matrix = builder.build().unwrap();
         let solutions = matrix
             .solve_all()
             .into_iter()
@@ -359,7 +2672,7 @@ mod tests {
         builder.add_option(2, ["b", "c:2"]);
         builder.add_option(3, ["a", "b", "c:3"]);
 
-        let mut matrix = builder.build().unwrap();
+        let matrix = builder.build().unwrap();
         let solutions = matrix
             .solve_all()
             .into_iter()
@@ -375,4 +2688,442 @@ mod tests {
             "Should only have [3] as a solution"
         );
     }
+
+    #[test]
+    fn test_unsatisfiable() {
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["a", "b", "c"]);
+        builder.add_option(1, ["a", "b"]);
+        builder.add_option(2, ["b", "c"]);
+
+        let matrix = builder.build().unwrap();
+        assert!(!matrix.is_satisfiable());
+        assert_eq!(Solver::new(&matrix).count_solutions(Limit::All), 0);
+    }
+
+    #[test]
+    fn test_never_branches_on_secondary_items() {
+        // Interleave secondary items (x, y) with primary items (a, b), so that
+        // the secondary items are not all numbered after the primary ones.
+        let (x, a, y, b) = (0, 1, 2, 3);
+        let mut matrix = Matrix::with_primary_items(4, &[ItemId::new(a), ItemId::new(b)]);
+        let item = |i| ColoredItem::new(ItemId::new(i));
+        matrix.add_option(1, &[item(a), item(x)]);
+        matrix.add_option(2, &[item(b)]);
+        matrix.add_option(3, &[item(a), item(y)]);
+        matrix.add_option(4, &[item(b), item(x)]);
+
+        let solver = Solver::new(&matrix);
+        assert_eq!(solver.count_items(), [2, 2, 1, 2]);
+        let chosen = solver.choose_next_item().unwrap();
+        assert!(matrix.is_primary(chosen), "branched on {chosen:?}");
+
+        let mut solutions = matrix
+            .solve_all()
+            .into_iter()
+            .map(|s| {
+                let mut meanings = s.meanings(&matrix);
+                meanings.sort();
+                meanings
+            })
+            .collect::<Vec<_>>();
+        solutions.sort();
+        assert_eq!(solutions, [[&1, &2], [&2, &3], [&3, &4]]);
+    }
+
+    #[test]
+    fn test_required_item_no_duplicate_solutions() {
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["a", "b"]);
+        builder.add_required_colored_item("r");
+        builder.add_option(1, ["a", "r:red"]);
+        builder.add_option(2, ["b", "r:red"]);
+        builder.add_option(3, ["a"]);
+        builder.add_option(4, ["a"]);
+        builder.add_option(5, ["b"]);
+        builder.add_option(6, ["b"]);
+
+        let matrix = builder.build().unwrap();
+        let solver = Solver::new(&matrix);
+        let r = ItemId::new(2);
+        assert_eq!(solver.choose_next_item(), Some(r));
+
+        let mut solutions = matrix
+            .solve_all()
+            .into_iter()
+            .map(|s| {
+                let mut meanings = s.meanings(&matrix);
+                meanings.sort();
+                meanings
+            })
+            .collect::<Vec<_>>();
+        solutions.sort();
+        assert_eq!(
+            solutions,
+            [[&1, &2], [&1, &5], [&1, &6], [&2, &3], [&2, &4]]
+        );
+    }
+
+    #[test]
+    fn test_solution_equality_ignores_order() {
+        let ids = |ids: &[usize]| Solution {
+            option_ids: ids.iter().copied().map(OptionId::new).collect(),
+        };
+        assert_eq!(ids(&[3, 1, 2]), ids(&[1, 2, 3]));
+        assert_ne!(ids(&[1, 2]), ids(&[1, 2, 3]));
+        assert_ne!(ids(&[1, 2]), ids(&[1, 3]));
+
+        let set: HashSet<Solution> = [ids(&[3, 1, 2]), ids(&[2, 3, 1]), ids(&[1, 3])]
+            .into_iter()
+            .collect();
+        assert_eq!(set.len(), 2);
+        assert!(set.contains(&ids(&[1, 2, 3])));
+    }
+
+    #[test]
+    fn test_propagation_finds_same_solutions() {
+        #[rustfmt::skip]
+        let clues = [
+            Some(1), None,    None,    None,
+            None,    None,    None,    None,
+            None,    None,    None,    None,
+            None,    None,    None,    Some(2),
+        ];
+        let matrix = crate::samples::sudoku_n(2, &clues);
+        let solve = |propagation| {
+            let mut solver = Solver::new(&matrix);
+            solver.set_propagation(propagation);
+            solver.solve_all().into_iter().collect::<HashSet<_>>()
+        };
+        let plain = solve(false);
+        assert!(plain.len() > 1);
+        assert_eq!(plain, solve(true));
+    }
+
+    fn assert_default_policy_matches<T>(matrix: &Matrix<T>) {
+        let expected: HashSet<Solution> = matrix.solve_all().into_iter().collect();
+        let mut solver = Solver::new(matrix);
+        solver.set_color_policy(Box::new(crate::DefaultColorPolicy));
+        let solutions: HashSet<Solution> = solver.solve_all().into_iter().collect();
+        assert_eq!(solutions, expected);
+    }
+
+    #[test]
+    fn test_default_color_policy_matches_builtin_rules() {
+        assert_default_policy_matches(&crate::samples::toy());
+        assert_default_policy_matches(&crate::samples::word_fill(
+            &["..", ".."],
+            &["AB", "BA", "AA", "BB"],
+        ));
+
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["p", "q"]);
+        builder.add_secondary_item("x");
+        builder.add_option("p red", ["p", "x:red"]);
+        builder.add_option("q not red", ["q", "x:!red"]);
+        builder.add_option("q plain", ["q"]);
+        let forbidding = builder.build().unwrap();
+        assert_default_policy_matches(&forbidding);
+        assert_eq!(forbidding.solve_all().len(), 1);
+    }
+
+    #[test]
+    fn test_forbidden_colors() {
+        // With only_p_blue, p has fewer options, so the solver commits x to
+        // a color before choosing an option that forbids one; otherwise, q
+        // comes first and the forbidden color hides options for p.
+        for (only_p_blue, expected) in [(false, 4), (true, 2)] {
+            let mut builder = Matrix::builder();
+            builder.add_primary_items(["p", "q"]);
+            builder.add_secondary_item("x");
+            builder.add_option(1, ["p", "x:red"]);
+            builder.add_option(2, ["q", "x:!red"]);
+            builder.add_option(3, ["q", "x:blue"]);
+            builder.add_option(4, ["p", "x:blue"]);
+            if !only_p_blue {
+                builder.add_option(5, ["p"]);
+            }
+            let matrix = builder.build().unwrap();
+            let solutions = matrix.solve_all();
+            assert_eq!(solutions.len(), expected);
+            for solution in &solutions {
+                let meanings = solution.meanings(&matrix);
+                assert!(!(meanings.contains(&&1) && meanings.contains(&&2)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_color_aware_counts() {
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["a", "b"]);
+        builder.add_secondary_item("x");
+        builder.add_option(1, ["a", "x:red"]);
+        builder.add_option(2, ["a", "x:blue"]);
+        builder.add_option(3, ["b", "x:red"]);
+        builder.add_option(4, ["b"]);
+
+        let matrix = builder.build().unwrap();
+        let mut solver = Solver::new(&matrix);
+        solver.set_color_aware_counts(true);
+        assert_eq!(solver.count_items(), [2, 2, 3]);
+
+        // Pretend x was committed to red without hiding option 2.
+        let red = matrix
+            .get_option(OptionId::new(0))
+            .color_of(ItemId::new(2))
+            .unwrap();
+        solver.committed_colors.insert(ItemId::new(2), red);
+        assert_eq!(solver.count_items(), [1, 2, 2]);
+        solver.set_color_aware_counts(false);
+        assert_eq!(solver.count_items(), [2, 2, 3]);
+    }
+
+    #[test]
+    fn test_color_aware_counts_with_free_secondary_items() {
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["a", "b"]);
+        builder.add_secondary_item("x");
+        builder.add_option(1, ["a", "x:red"]);
+        builder.add_option(2, ["b", "x"]);
+        builder.add_option(3, ["b", "x:blue"]);
+        builder.secondary_mode(SecondaryMode::Free);
+
+        let matrix = builder.build().unwrap();
+        let mut solver = Solver::new(&matrix);
+        solver.set_color_aware_counts(true);
+        let red = matrix
+            .get_option(OptionId::new(0))
+            .color_of(ItemId::new(2))
+            .unwrap();
+        solver.committed_colors.insert(ItemId::new(2), red);
+
+        // The uncolored use of x still counts; only the blue one doesn't.
+        assert_eq!(solver.count_items(), [1, 1, 2]);
+    }
+
+    #[test]
+    fn test_free_secondary_items_still_respect_colors() {
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["a", "b", "c"]);
+        builder.add_secondary_item("x");
+        builder.add_option(1, ["a", "x"]);
+        builder.add_option(2, ["b", "x:red"]);
+        builder.add_option(3, ["c", "x:red"]);
+        builder.add_option(4, ["c", "x:blue"]);
+        builder.secondary_mode(SecondaryMode::Free);
+        let matrix = builder.build().unwrap();
+
+        // The uncolored use of x doesn't clash with anything, but red and
+        // blue still can't both be chosen.
+        let solutions = matrix.solve_all();
+        assert_eq!(solutions.len(), 1);
+        let mut meanings = solutions[0].meanings(&matrix);
+        meanings.sort();
+        assert_eq!(meanings, [&1, &2, &3]);
+    }
+
+    #[test]
+    fn test_solution_order_is_reproducible() {
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["a", "b"]);
+        builder.add_secondary_item("x");
+        builder.add_option(1, ["a", "x:red"]);
+        builder.add_option(2, ["a", "x:blue"]);
+        builder.add_option(3, ["b", "x:red"]);
+        builder.add_option(4, ["b", "x:blue"]);
+        builder.add_option(5, ["a"]);
+        builder.add_option(6, ["b"]);
+        let matrix = builder.build().unwrap();
+
+        let mut each = Vec::new();
+        Solver::new(&matrix).solve_each_with_colors(Limit::All, |solution, colors| {
+            let meanings: Vec<_> = solution.meanings(&matrix).into_iter().copied().collect();
+            let x = colors.get(&ItemId::new(2)).map(|color| color.index());
+            each.push((meanings, x));
+        });
+        // Snapshot tests depend on this exact order, so changing it should
+        // be a deliberate choice.
+        assert_eq!(
+            each,
+            [
+                (vec![5, 6], None),
+                (vec![5, 4], Some(1)),
+                (vec![5, 3], Some(0)),
+                (vec![2, 6], Some(1)),
+                (vec![2, 4], Some(1)),
+                (vec![1, 6], Some(0)),
+                (vec![1, 3], Some(0)),
+            ]
+        );
+        let meanings: Vec<Vec<_>> = matrix
+            .solve_all()
+            .iter()
+            .map(|solution| solution.meanings(&matrix).into_iter().copied().collect())
+            .collect();
+        let expected: Vec<_> = each.into_iter().map(|(meanings, _)| meanings).collect();
+        assert_eq!(meanings, expected);
+    }
+
+    /// Checks that branching on secondary items finds the same solutions,
+    /// each exactly once.
+    fn check_secondary_branching<T>(matrix: &Matrix<T>) {
+        let mut plain = Solver::new(matrix);
+        let expected: HashSet<Solution> = plain.solve_all().into_iter().collect();
+        for propagation in [false, true] {
+            let mut solver = Solver::new(matrix);
+            solver.set_secondary_branching(true);
+            solver.set_propagation(propagation);
+            let solutions = solver.solve_all();
+            let found: HashSet<Solution> = solutions.iter().cloned().collect();
+            assert_eq!(found.len(), solutions.len(), "duplicate solutions");
+            assert_eq!(found, expected);
+        }
+    }
+
+    #[test]
+    fn test_secondary_branching_finds_same_solutions() {
+        let queens = |n: usize| {
+            let mut builder = Matrix::builder();
+            builder.add_primary_items((0..n).map(|i| format!("R{i}")));
+            builder.add_primary_items((0..n).map(|i| format!("C{i}")));
+            builder.add_secondary_items((0..2 * n - 1).map(|i| format!("D{i}")));
+            builder.add_secondary_items((0..2 * n - 1).map(|i| format!("E{i}")));
+            for row in 0..n {
+                for col in 0..n {
+                    let (d, e) = (row + col, n - 1 - row + col);
+                    builder.add_option(
+                        (row, col),
+                        [
+                            format!("R{row}"),
+                            format!("C{col}"),
+                            format!("D{d}"),
+                            format!("E{e}"),
+                        ],
+                    );
+                }
+            }
+            builder.build().unwrap()
+        };
+        let word_fill = crate::samples::word_fill(
+            &["...", "...", "..."],
+            &["ace", "bat", "cab", "abc", "tab", "eat", "ate", "tea"],
+        );
+        let coloring = crate::samples::graph_coloring(&[(0, 1), (1, 2), (2, 3), (3, 0), (0, 2)], 3);
+
+        for n in 4..=7 {
+            check_secondary_branching(&queens(n));
+        }
+        check_secondary_branching(&word_fill);
+        check_secondary_branching(&coloring);
+        check_secondary_branching(&crate::samples::toy());
+    }
+
+    /// Returns `true` if the search branched on `x` anywhere in the tree.
+    fn branches_on(tree: &SearchTree, x: ItemId) -> bool {
+        match tree {
+            SearchTree::Branch { item, children } => {
+                *item == x || children.iter().any(|(_, child)| branches_on(child, x))
+            }
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn test_committed_color_with_secondary_branching() {
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["a", "b"]);
+        builder.add_secondary_item("x");
+        builder.add_option(1, ["a", "x:red"]);
+        builder.add_option(2, ["a"]);
+        builder.add_option(3, ["a"]);
+        builder.add_option(4, ["b", "x:red"]);
+        builder.add_option(5, ["b"]);
+        builder.add_option(6, ["b"]);
+        builder.add_option(7, ["a", "x:blue"]);
+        let matrix = builder.build().unwrap();
+        let (x, red) = (ItemId::new(2), Color::new(0));
+
+        let mut plain = Solver::new(&matrix);
+        plain.commit_color(x, red).unwrap();
+        let expected: HashSet<Solution> = plain.solve_all().into_iter().collect();
+        assert_eq!(expected.len(), 9);
+
+        let mut solver = Solver::new(&matrix);
+        solver.set_secondary_branching(true);
+        solver.commit_color(x, red).unwrap();
+        let solutions = solver.solve_all();
+        let found: HashSet<Solution> = solutions.iter().cloned().collect();
+        assert_eq!(found.len(), solutions.len(), "duplicate solutions");
+        assert_eq!(found, expected);
+
+        // x's color is settled, so there's nothing to branch on.
+        solver.reset();
+        solver.commit_color(x, red).unwrap();
+        assert!(!branches_on(&solver.solve_tree(Limit::All), x));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn test_solve_variants_trace_the_same_search() {
+        let matrix = crate::samples::word_fill(&["..", ".."], &["AB", "BA", "AA", "BB"]);
+        let trace = |run: &dyn Fn(&mut Solver<'_, crate::samples::Word>)| {
+            let mut trace = Vec::new();
+            let mut solver = Solver::new(&matrix);
+            solver.set_logger(&mut trace);
+            run(&mut solver);
+            drop(solver);
+            String::from_utf8(trace).unwrap()
+        };
+        let expected = trace(&|solver| {
+            solver.solve_all();
+        });
+        assert!(expected.contains("backtrack\n"));
+        assert_eq!(
+            trace(&|solver| {
+                solver.solve_with_budget(usize::MAX, Limit::All);
+            }),
+            expected
+        );
+        assert_eq!(
+            trace(&|solver| {
+                solver.solve_with_progress(Limit::All, |_| {});
+            }),
+            expected
+        );
+        assert_eq!(
+            trace(&|solver| {
+                solver.solve_tree(Limit::All);
+            }),
+            expected
+        );
+        assert_eq!(
+            trace(&|solver| {
+                solver.iter_with_colors().for_each(drop);
+            }),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_distinct_meanings_needs_only_eq_and_hash() {
+        // Not `Ord`, so the meanings can't be sorted.
+        #[derive(Debug, PartialEq, Eq, Hash)]
+        struct Meaning(&'static str);
+
+        let mut builder = Matrix::builder();
+        builder.add_primary_items(["a", "b", "c"]);
+        builder.add_option(Meaning("x"), ["a"]);
+        builder.add_option(Meaning("y"), ["b", "c"]);
+        builder.add_option(Meaning("y"), ["a", "b"]);
+        builder.add_option(Meaning("x"), ["c"]);
+        builder.add_option(Meaning("x"), ["b"]);
+        let matrix = builder.build().unwrap();
+
+        // {x, y} comes up twice, in different orders, and {x, x, x} once.
+        assert_eq!(matrix.solve_all().len(), 3);
+        let distinct = matrix.solve_distinct_meanings(5);
+        assert_eq!(distinct.len(), 2);
+        assert!(distinct.iter().any(|meanings| meanings.len() == 3));
+    }
 }
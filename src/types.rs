@@ -1,5 +1,11 @@
+use crate::collections::HashMap;
+use crate::BuildError;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
 /// ID of an option (row) in the matrix.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct OptionId(usize);
 
 impl OptionId {
@@ -14,10 +20,27 @@ impl OptionId {
     pub(crate) fn index(self) -> usize {
         self.0
     }
+
+    /// Returns the underlying index, the inverse of `OptionId::new()`.  This is
+    /// handy for using IDs as indices into your own arrays.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::OptionId;
+    ///
+    /// let option = OptionId::new(7);
+    /// assert_eq!(option.get(), 7);
+    /// ```
+    #[must_use]
+    pub fn get(self) -> usize {
+        self.0
+    }
 }
 
 /// ID of an item (column) in the matrix.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ItemId(usize);
 
 impl ItemId {
@@ -32,20 +55,216 @@ impl ItemId {
     pub(crate) fn index(self) -> usize {
         self.0
     }
+
+    /// Returns the underlying index, the inverse of `ItemId::new()`.  This is
+    /// handy for using IDs as indices into your own arrays.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use xcc::ItemId;
+    ///
+    /// let item = ItemId::new(7);
+    /// assert_eq!(item.get(), 7);
+    /// ```
+    #[must_use]
+    pub fn get(self) -> usize {
+        self.0
+    }
+}
+
+/// A permutation of the items of a matrix, such as a symmetry found by
+/// `Matrix::find_option_automorphisms()`.
+///
+/// # Example
+///
+/// ```
+/// use xcc::{ItemId, Permutation};
+///
+/// let swap = Permutation::transposition(4, ItemId::new(1), ItemId::new(3));
+/// assert_eq!(swap.image(ItemId::new(1)), ItemId::new(3));
+/// assert_eq!(swap.image(ItemId::new(2)), ItemId::new(2));
+/// assert_eq!(swap.moved_items(), [ItemId::new(1), ItemId::new(3)]);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Permutation {
+    /// The image of each item, indexed by item.
+    images: Vec<ItemId>,
+}
+
+impl Permutation {
+    /// Creates the permutation of `num_items` items that swaps `a` and `b`
+    /// and leaves every other item where it is.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `a` or `b` isn't less than `num_items`.
+    #[must_use]
+    pub fn transposition(num_items: usize, a: ItemId, b: ItemId) -> Self {
+        let mut images: Vec<ItemId> = (0..num_items).map(ItemId::new).collect();
+        images.swap(a.index(), b.index());
+        Permutation { images }
+    }
+
+    /// Returns the item that `item` is mapped to.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `item` is out of range.
+    #[must_use]
+    pub fn image(&self, item: ItemId) -> ItemId {
+        self.images[item.index()]
+    }
+
+    /// Returns the items that the permutation moves, in ascending order.
+    #[must_use]
+    pub fn moved_items(&self) -> Vec<ItemId> {
+        self.images
+            .iter()
+            .enumerate()
+            .filter(|&(i, image)| image.index() != i)
+            .map(|(i, _)| ItemId::new(i))
+            .collect()
+    }
 }
 
 /// Color of an item.
+///
+/// Colors are stored in 32 bits, since every option carries one for each of
+/// its colored items and even large problems use far fewer colors than that.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
-pub struct Color(usize);
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Color(u32);
 
 impl Color {
     /// Creates a new `Color`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` doesn't fit in a `u32`.
     #[must_use]
     pub fn new(id: usize) -> Self {
-        Color(id)
+        Color(u32::try_from(id).expect("color ID out of range"))
+    }
+
+    /// Returns the numeric ID of the color.
+    #[must_use]
+    pub(crate) fn index(self) -> usize {
+        self.0 as usize
     }
 }
 
+/// Assigns `Color`s to color names, giving each new name the next unused
+/// ID.  A builder interns the color names in its options when it builds the
+/// matrix; seed several builders with the same interner, using
+/// `Builder::with_color_interner()`, and a color name means the same `Color`
+/// in every matrix they build.
+///
+/// # Examples
+///
+/// ```
+/// use xcc::{Color, ColorInterner};
+///
+/// let mut colors = ColorInterner::new();
+/// assert_eq!(colors.intern("red"), Color::new(0));
+/// assert_eq!(colors.intern("blue"), Color::new(1));
+/// assert_eq!(colors.intern("red"), Color::new(0));
+/// assert_eq!(colors.get("blue"), Some(Color::new(1)));
+/// assert_eq!(colors.name(Color::new(1)), Some("blue"));
+/// assert_eq!(colors.len(), 2);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ColorInterner {
+    names: Vec<String>,
+    ids: HashMap<String, Color>,
+}
+
+impl ColorInterner {
+    /// Creates an interner with no colors.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the color for `name`, allocating a new one if the name hasn't
+    /// been seen before.
+    pub fn intern(&mut self, name: &str) -> Color {
+        if let Some(&color) = self.ids.get(name) {
+            return color;
+        }
+        let color = Color::new(self.names.len());
+        self.names.push(name.to_string());
+        self.ids.insert(name.to_string(), color);
+        color
+    }
+
+    /// Returns the color for `name`, if it has been interned.
+    #[must_use]
+    pub fn get(&self, name: &str) -> Option<Color> {
+        self.ids.get(name).copied()
+    }
+
+    /// Returns the name of a color, if it came from this interner.
+    #[must_use]
+    pub fn name(&self, color: Color) -> Option<&str> {
+        self.names.get(color.index()).map(String::as_str)
+    }
+
+    /// Returns the number of colors interned so far.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    /// Returns `true` if no colors have been interned.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+}
+
+/// Returns `true` if two options can both use a secondary item, given the
+/// color each one gives it.  They can if both give it the same color.  An
+/// uncolored use of an item clashes with any other use, colored or not.
+///
+/// This is the rule for `SecondaryMode::AtMostOnce`, the default.  Primary
+/// items can never be shared, whatever their colors.
+///
+/// # Examples
+///
+/// ```
+/// use xcc::{colors_compatible, Color};
+///
+/// let (red, blue) = (Color::new(0), Color::new(1));
+/// assert!(colors_compatible(Some(red), Some(red)));
+/// assert!(!colors_compatible(Some(red), Some(blue)));
+/// assert!(!colors_compatible(Some(red), None));
+/// assert!(!colors_compatible(None, None));
+/// ```
+#[must_use]
+pub fn colors_compatible(a: Option<Color>, b: Option<Color>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// How the solver treats a secondary item that appears in an option without
+/// a color.
+///
+/// Colored secondary items behave the same way in either mode: all options
+/// that use the item must agree on its color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum SecondaryMode {
+    /// An uncolored secondary item may be used by at most one option in a
+    /// solution.  This is the behavior described in TAOCP.
+    #[default]
+    AtMostOnce,
+    /// An uncolored secondary item places no constraint on the solution, so
+    /// choosing an option never hides another option because they share it.
+    Free,
+}
+
 /// Represents an item in the Dancing Links data structure that may or may not have
 /// a color assigned to it.
 ///
@@ -62,7 +281,7 @@ impl Color {
 /// let item = ColoredItem::with_color(ItemId::new(1), Color::new(100));
 /// assert_eq!(item.color(), Some(Color::new(100)));
 /// ```
-#[derive(Clone, Copy)]
+#[derive(Debug, Clone, Copy)]
 pub struct ColoredItem {
     item_id: ItemId,
     color: Option<Color>,
@@ -115,4 +334,83 @@ impl ColoredItem {
             color: Some(color),
         }
     }
+
+    /// Parses an item written as `"name"` or `"name:color"`, the notation
+    /// accepted by [`Builder`](crate::Builder) for an option that gives an
+    /// item a color.
+    ///
+    /// `resolve` maps an item name to its ID, and `intern` maps a color
+    /// name to a `Color`, typically allocating a new one the first time a
+    /// name is seen.
+    ///
+    /// # Errors
+    ///
+    /// Returns `BuildError::ForbiddenColorUnsupported` for an item written
+    /// as `"name:!color"`.  The builder reads that as forbidding the color,
+    /// which a `ColoredItem` can't express; use `Matrix::forbid_color()`
+    /// instead.
+    ///
+    /// # Examples
+    /// ```
+    /// use std::collections::HashMap;
+    /// use xcc::{Color, ColoredItem, ErrorKind, ItemId};
+    ///
+    /// let items = HashMap::from([("x", ItemId::new(0)), ("y", ItemId::new(1))]);
+    /// let mut colors = HashMap::new();
+    /// let mut intern = |c: &str| {
+    ///     let next = Color::new(colors.len());
+    ///     *colors.entry(c.to_string()).or_insert(next)
+    /// };
+    ///
+    /// let item = ColoredItem::parse("y:red", |name| items[name], &mut intern).unwrap();
+    /// assert_eq!(item.item(), ItemId::new(1));
+    /// assert_eq!(item.color(), Some(Color::new(0)));
+    ///
+    /// let item = ColoredItem::parse("x", |name| items[name], &mut intern).unwrap();
+    /// assert_eq!(item.item(), ItemId::new(0));
+    /// assert_eq!(item.color(), None);
+    ///
+    /// let err = ColoredItem::parse("x:!red", |name| items[name], &mut intern).unwrap_err();
+    /// assert_eq!(err.kind(), ErrorKind::ForbiddenColorUnsupported);
+    /// assert_eq!(err.item_name(), Some("x"));
+    /// ```
+    pub fn parse(
+        s: &str,
+        resolve: impl Fn(&str) -> ItemId,
+        mut intern: impl FnMut(&str) -> Color,
+    ) -> Result<Self, BuildError> {
+        match split_item(s) {
+            (name, ItemColorName::None) => Ok(ColoredItem::new(resolve(name))),
+            (name, ItemColorName::Is(color)) => {
+                Ok(ColoredItem::with_color(resolve(name), intern(color)))
+            }
+            (name, ItemColorName::IsNot(_)) => {
+                Err(BuildError::ForbiddenColorUnsupported(name.to_string()))
+            }
+        }
+    }
+}
+
+/// The color part of an item written in the builder's notation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ItemColorName<'s> {
+    /// `"name"`: the item has no color.
+    None,
+    /// `"name:color"`: the item has this color.
+    Is(&'s str),
+    /// `"name:!color"`: the item must not have this color.
+    IsNot(&'s str),
+}
+
+/// Splits an item written as `"name"`, `"name:color"` or `"name:!color"`
+/// into its name and color.  This is the one place that notation is parsed,
+/// for both `ColoredItem::parse()` and `Builder`.
+pub(crate) fn split_item(s: &str) -> (&str, ItemColorName<'_>) {
+    let Some((name, color)) = s.split_once(':') else {
+        return (s, ItemColorName::None);
+    };
+    match color.strip_prefix('!') {
+        Some(color) => (name, ItemColorName::IsNot(color)),
+        None => (name, ItemColorName::Is(color)),
+    }
 }
@@ -6,10 +6,10 @@
 ///
 /// - `Unique::None` means that the problem is unsolvable.
 /// - `Unique::One(solution)` means that the problem has exactly one solution:
-///    after finding it, the solver exhaustively searched for other solutions,
-///    and found none.
+///   after finding it, the solver exhaustively searched for other solutions,
+///   and found none.
 /// - `Unique::Many(solution1, solution2)` means that the problem has at least
-///     two solutions, and two such solutions are `solution1` and `solution2`.
+///   two solutions, and two such solutions are `solution1` and `solution2`.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Unique<T> {
     /// The problem is unsolvable.